@@ -0,0 +1,102 @@
+use serde_json::{Number, Value, json};
+use crate::engine::{self, PathElement};
+
+/// A difference between two documents at a single location
+#[derive(Debug, PartialEq)]
+pub enum Change<'a> {
+    /// The location only exists in the right document
+    Added { path: Vec<PathElement>, value: &'a Value },
+    /// The location only exists in the left document
+    Removed { path: Vec<PathElement>, value: &'a Value },
+    /// The location holds different values in both documents
+    Changed { path: Vec<PathElement>, old: &'a Value, new: &'a Value },
+}
+
+/// Compare two documents and list the differences in document order
+///
+/// Objects are compared key by key and arrays element by element, so a
+/// change deep inside a document is reported at its own location. Numbers
+/// are compared by value, so `1` and `1.0` are equal even though one format
+/// may have stored an integer and the other a float.
+pub fn diff<'a>(left: &'a Value, right: &'a Value, base: &[PathElement]) -> Vec<Change<'a>> {
+    let mut changes = Vec::new();
+    let mut path = base.to_vec();
+    collect_changes(left, right, &mut path, &mut changes);
+    changes
+}
+
+fn collect_changes<'a>(left: &'a Value, right: &'a Value, path: &mut Vec<PathElement>, changes: &mut Vec<Change<'a>>) {
+    match (left, right) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                path.push(PathElement::Key(key.clone()));
+                match new.get(key) {
+                    Some(new_value) => collect_changes(old_value, new_value, path, changes),
+                    None => changes.push(Change::Removed { path: path.clone(), value: old_value }),
+                }
+                path.pop();
+            }
+            for (key, new_value) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+                path.push(PathElement::Key(key.clone()));
+                changes.push(Change::Added { path: path.clone(), value: new_value });
+                path.pop();
+            }
+        },
+        (Value::Array(old), Value::Array(new)) => {
+            for idx in 0..old.len().max(new.len()) {
+                path.push(PathElement::Index(idx));
+                match (old.get(idx), new.get(idx)) {
+                    (Some(old_value), Some(new_value)) => collect_changes(old_value, new_value, path, changes),
+                    (Some(old_value), None) => changes.push(Change::Removed { path: path.clone(), value: old_value }),
+                    (None, Some(new_value)) => changes.push(Change::Added { path: path.clone(), value: new_value }),
+                    (None, None) => {},
+                }
+                path.pop();
+            }
+        },
+        (Value::Number(old), Value::Number(new)) if numbers_equal(old, new) => {},
+        _ if left != right => changes.push(Change::Changed { path: path.clone(), old: left, new: right }),
+        _ => {},
+    }
+}
+
+/// Compare numbers by value
+///
+/// Integers are compared exactly, since integers above 2^53 lose precision as
+/// floats, and only a comparison involving a float goes through `f64`.
+pub fn numbers_equal(left: &Number, right: &Number) -> bool {
+    if left.is_f64() || right.is_f64() {
+        left.as_f64() == right.as_f64()
+    } else {
+        left.as_i64() == right.as_i64() && left.as_u64() == right.as_u64()
+    }
+}
+
+/// Render changes as one line each: `+` for added, `-` for removed and `~` for changed
+pub fn to_text(changes: &[Change]) -> String {
+    changes.iter()
+        .map(|change| match change {
+            Change::Added { path, value } => format!("+ {}: {}\n", engine::normalized_path(path), value),
+            Change::Removed { path, value } => format!("- {}: {}\n", engine::normalized_path(path), value),
+            Change::Changed { path, old, new } => format!("~ {}: {} -> {}\n", engine::normalized_path(path), old, new),
+        })
+        .collect()
+}
+
+/// Render changes as an array of `{"change", "path", ...}` objects
+pub fn to_json(changes: &[Change]) -> Value {
+    let changes = changes.iter()
+        .map(|change| match change {
+            Change::Added { path, value } => json!({
+                "change": "added", "path": engine::normalized_path(path), "value": value
+            }),
+            Change::Removed { path, value } => json!({
+                "change": "removed", "path": engine::normalized_path(path), "value": value
+            }),
+            Change::Changed { path, old, new } => json!({
+                "change": "changed", "path": engine::normalized_path(path), "old": old, "new": new
+            }),
+        })
+        .collect();
+    Value::Array(changes)
+}
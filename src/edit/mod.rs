@@ -0,0 +1,206 @@
+mod patch;
+mod toml_document;
+mod yaml_document;
+
+use anyhow::{Context, Result, anyhow};
+use serde_json::Value;
+use crate::engine::{self, PathElement};
+use crate::parser::{PathSegment, Query};
+
+pub use patch::{plan_patch, to_patch};
+pub use toml_document::apply_to_toml;
+pub use yaml_document::apply_to_yaml;
+
+/// A single change to a document, addressed by the location of the node it touches
+///
+/// Operations are planned against the parsed value and then applied in order,
+/// either to that value or to a format-preserving representation of the file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    /// Insert a new member into an existing object, or a new element into an
+    /// existing array before the given index (or at its end)
+    Add { path: Vec<PathElement>, value: Value },
+    /// Replace an existing node
+    Replace { path: Vec<PathElement>, value: Value },
+    /// Remove an existing object member or array element
+    Remove { path: Vec<PathElement> },
+}
+
+/// Plan the operations that assign `new_value` to every node matched by a query
+///
+/// With `create`, missing objects along a path made only of field accesses are
+/// created, so that the final field can be added.
+pub fn plan_set(document: &Value, query: &Query, new_value: &Value, create: bool) -> Result<Vec<Operation>> {
+    if create {
+        if !query.is_singular() {
+            return Err(anyhow!("Missing values can only be created for paths of field and index accesses"));
+        }
+        if let Some(operation) = plan_create(document, &query.path_segments, new_value)? {
+            return Ok(vec![operation]);
+        }
+    }
+
+    // Nodes nested in another match are replaced along with it
+    let paths = outermost(matched_paths(document, query)?);
+    Ok(paths.into_iter()
+        .map(|path| Operation::Replace { path, value: new_value.clone() })
+        .collect())
+}
+
+/// Plan the operations that remove every node matched by a query
+pub fn plan_delete(document: &Value, query: &Query) -> Result<Vec<Operation>> {
+    let mut paths = outermost(matched_paths(document, query)?);
+    if paths.iter().any(|path| path.is_empty()) {
+        return Err(anyhow!("Cannot delete the document root"));
+    }
+
+    // Delete in reverse document order so that higher array indices go first
+    // and the positions of earlier matches stay valid
+    paths.reverse();
+    Ok(paths.into_iter()
+        .map(|path| Operation::Remove { path })
+        .collect())
+}
+
+/// Apply operations in order to a parsed document
+pub fn apply(document: &mut Value, operations: &[Operation]) -> Result<()> {
+    for operation in operations {
+        match operation {
+            Operation::Add { path, value } => {
+                let (last, parent_path) = path.split_last()
+                    .context("Cannot add the document root")?;
+                match (last, node_mut(document, parent_path)) {
+                    (PathElement::Key(key), Some(Value::Object(obj))) => {
+                        obj.insert(key.clone(), value.clone());
+                    },
+                    (PathElement::Index(idx), Some(Value::Array(arr))) if *idx <= arr.len() => {
+                        arr.insert(*idx, value.clone());
+                    },
+                    _ => return Err(anyhow!(
+                        "Cannot add {}: no object or array to add it to", engine::normalized_path(path)
+                    )),
+                }
+            },
+            Operation::Replace { path, value } => {
+                let node = node_mut(document, path)
+                    .context(format!("No value at {}", engine::normalized_path(path)))?;
+                *node = value.clone();
+            },
+            Operation::Remove { path } => {
+                let (last, parent_path) = path.split_last()
+                    .context("Cannot delete the document root")?;
+                let removed = match (last, node_mut(document, parent_path)) {
+                    (PathElement::Key(key), Some(Value::Object(obj))) => obj.shift_remove(key).is_some(),
+                    (PathElement::Index(idx), Some(Value::Array(arr))) if *idx < arr.len() => {
+                        arr.remove(*idx);
+                        true
+                    },
+                    _ => false,
+                };
+                if !removed {
+                    return Err(anyhow!("No value at {}", engine::normalized_path(path)));
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Return a mutable reference to the node at a location, if it exists
+pub fn node_mut<'a>(document: &'a mut Value, path: &[PathElement]) -> Option<&'a mut Value> {
+    let mut current = document;
+    for element in path {
+        current = match (element, current) {
+            (PathElement::Key(key), Value::Object(obj)) => obj.get_mut(key)?,
+            (PathElement::Index(idx), Value::Array(arr)) => arr.get_mut(*idx)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+/// Locations of every node matched by a query, sorted in document order without duplicates
+fn matched_paths(document: &Value, query: &Query) -> Result<Vec<Vec<PathElement>>> {
+    let mut paths: Vec<Vec<PathElement>> = engine::locate(document, query)?
+        .into_iter()
+        .map(|m| m.path)
+        .collect();
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+/// Drop every path that lies inside another path of a sorted list
+fn outermost(paths: Vec<Vec<PathElement>>) -> Vec<Vec<PathElement>> {
+    let mut kept: Vec<Vec<PathElement>> = Vec::new();
+    for path in paths {
+        // In sorted order an ancestor always comes right before its descendants
+        if kept.last().is_some_and(|ancestor| path.starts_with(ancestor)) {
+            continue;
+        }
+        kept.push(path);
+    }
+    kept
+}
+
+/// Plan the operation creating the first missing field along a singular path
+///
+/// Returns `None` when every field already exists.
+fn plan_create(document: &Value, segments: &[PathSegment], new_value: &Value) -> Result<Option<Operation>> {
+    let mut current = document;
+    let mut path = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        current = match (segment, current) {
+            (PathSegment::Field(name) | PathSegment::Token(name), Value::Object(obj)) => {
+                path.push(PathElement::Key(name.clone()));
+                match obj.get(name) {
+                    Some(next) => next,
+                    None => {
+                        let value = nest(&segments[i + 1..], new_value)?;
+                        return Ok(Some(Operation::Add { path, value }));
+                    },
+                }
+            },
+            // A null placeholder is replaced by the objects leading to the new value
+            (PathSegment::Field(_) | PathSegment::Token(_), Value::Null) => {
+                let value = nest(&segments[i..], new_value)?;
+                return Ok(Some(Operation::Replace { path, value }));
+            },
+            (PathSegment::Token(token), Value::Array(arr)) => {
+                let idx = engine::pointer_index(token)
+                    .context(format!("Invalid array index '{}'", token))?;
+                path.push(PathElement::Index(idx));
+                arr.get(idx).context(format!("Index {} out of bounds", idx))?
+            },
+            (PathSegment::Index(idx), Value::Array(arr)) => {
+                let len = arr.len() as i64;
+                let resolved = usize::try_from(if *idx < 0 { len + idx } else { *idx }).ok()
+                    .filter(|i| *i < arr.len())
+                    .context(format!("Index {} out of bounds", idx))?;
+                path.push(PathElement::Index(resolved));
+                &arr[resolved]
+            },
+            (PathSegment::Field(name) | PathSegment::Token(name), _) => {
+                return Err(anyhow!("Cannot create field '{}' inside a non-object value", name));
+            },
+            _ => return Err(anyhow!("Cannot access index on non-array value")),
+        };
+    }
+    Ok(None)
+}
+
+/// Wrap a value in one object per remaining field, innermost last
+fn nest(segments: &[PathSegment], value: &Value) -> Result<Value> {
+    let mut nested = value.clone();
+    for segment in segments.iter().rev() {
+        match segment {
+            PathSegment::Field(name) | PathSegment::Token(name) => {
+                let mut obj = serde_json::Map::new();
+                obj.insert(name.clone(), nested);
+                nested = Value::Object(obj);
+            },
+            _ => return Err(anyhow!("Missing array elements cannot be created")),
+        }
+    }
+    Ok(nested)
+}
@@ -0,0 +1,159 @@
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Value, json};
+use crate::diff;
+use crate::engine::{self, PathElement};
+use crate::parser::{self, PathSegment, Query};
+use super::{Operation, apply};
+
+/// Express operations as an RFC 6902 JSON Patch document
+pub fn to_patch(operations: &[Operation]) -> Value {
+    let patch = operations.iter()
+        .map(|operation| match operation {
+            Operation::Add { path, value } => json!({"op": "add", "path": engine::json_pointer(path), "value": value}),
+            Operation::Replace { path, value } => json!({"op": "replace", "path": engine::json_pointer(path), "value": value}),
+            Operation::Remove { path } => json!({"op": "remove", "path": engine::json_pointer(path)}),
+        })
+        .collect();
+    Value::Array(patch)
+}
+
+/// Plan the operations performing an RFC 6902 JSON Patch
+///
+/// Each patch operation is checked against the document as updated by the
+/// ones before it. `move` and `copy` become removals and additions, and a
+/// passing `test` plans nothing. The patch fails as a whole if any of its
+/// operations does.
+pub fn plan_patch(document: &Value, patch: &Value) -> Result<Vec<Operation>> {
+    let patch = patch.as_array()
+        .context("A JSON Patch must be an array of operations")?;
+
+    let mut current = document.clone();
+    let mut operations = Vec::new();
+    for (i, patch_operation) in patch.iter().enumerate() {
+        let planned = plan_patch_operation(&current, patch_operation)
+            .context(format!("Patch operation {} failed", i))?;
+        apply(&mut current, &planned)?;
+        operations.extend(planned);
+    }
+    Ok(operations)
+}
+
+/// Plan the operations for a single JSON Patch operation
+fn plan_patch_operation(document: &Value, patch_operation: &Value) -> Result<Vec<Operation>> {
+    let op = member_str(patch_operation, "op")?;
+    let pointer = member_str(patch_operation, "path")?;
+
+    match op {
+        "add" => {
+            let value = member(patch_operation, "value")?;
+            Ok(vec![plan_add(document, pointer, value.clone())?])
+        },
+        "remove" => {
+            let (path, _) = existing(document, pointer)?;
+            if path.is_empty() {
+                return Err(anyhow!("Cannot remove the document root"));
+            }
+            Ok(vec![Operation::Remove { path }])
+        },
+        "replace" => {
+            let value = member(patch_operation, "value")?;
+            let (path, _) = existing(document, pointer)?;
+            Ok(vec![Operation::Replace { path, value: value.clone() }])
+        },
+        "move" => {
+            let from_pointer = member_str(patch_operation, "from")?;
+            if from_pointer == pointer {
+                return Ok(Vec::new());
+            }
+            if pointer.starts_with(from_pointer) && pointer[from_pointer.len()..].starts_with('/') {
+                return Err(anyhow!("Cannot move {} into one of its own children", from_pointer));
+            }
+            let (from, value) = existing(document, from_pointer)?;
+            if from.is_empty() {
+                return Err(anyhow!("Cannot move the document root"));
+            }
+
+            // The target is resolved against the document without the moved value
+            let remove = Operation::Remove { path: from };
+            let mut without = document.clone();
+            apply(&mut without, std::slice::from_ref(&remove))?;
+            let add = plan_add(&without, pointer, value.clone())?;
+            Ok(vec![remove, add])
+        },
+        "copy" => {
+            let (_, value) = existing(document, member_str(patch_operation, "from")?)?;
+            Ok(vec![plan_add(document, pointer, value.clone())?])
+        },
+        "test" => {
+            let expected = member(patch_operation, "value")?;
+            let (_, value) = existing(document, pointer)?;
+            // Numbers compare by value, as RFC 6902 asks, so 1 passes a test for 1.0
+            if !diff::diff(value, expected, &[]).is_empty() {
+                return Err(anyhow!("Test failed: the value at {} is {}, not {}", pointer, value, expected));
+            }
+            Ok(Vec::new())
+        },
+        _ => Err(anyhow!("Unknown JSON Patch operation '{}'", op)),
+    }
+}
+
+/// Plan an `add`, which replaces an existing object member and inserts into arrays
+fn plan_add(document: &Value, pointer: &str, value: Value) -> Result<Operation> {
+    let query = parse_pointer(pointer)?;
+    let Some((last, parents)) = query.path_segments.split_last() else {
+        return Ok(Operation::Replace { path: Vec::new(), value });
+    };
+    let PathSegment::Token(token) = last else {
+        return Err(anyhow!("Invalid JSON Pointer: {}", pointer));
+    };
+
+    let parent_query = Query { path_segments: parents.to_vec(), recursive_paths: Vec::new() };
+    let (mut path, parent) = single_match(document, &parent_query, pointer)?;
+    match parent {
+        Value::Object(obj) => {
+            path.push(PathElement::Key(token.clone()));
+            Ok(if obj.contains_key(token) { Operation::Replace { path, value } } else { Operation::Add { path, value } })
+        },
+        Value::Array(arr) => {
+            // "-" refers to the position after the last element
+            let idx = if token == "-" { Some(arr.len()) } else { engine::pointer_index(token) }
+                .context(format!("Invalid array index '{}'", token))?;
+            if idx > arr.len() {
+                return Err(anyhow!("Index {} out of bounds", idx));
+            }
+            path.push(PathElement::Index(idx));
+            Ok(Operation::Add { path, value })
+        },
+        _ => Err(anyhow!("Cannot add {}: parent is neither an object nor an array", pointer)),
+    }
+}
+
+/// Resolve a pointer to an existing node
+fn existing<'a>(document: &'a Value, pointer: &str) -> Result<(Vec<PathElement>, &'a Value)> {
+    single_match(document, &parse_pointer(pointer)?, pointer)
+}
+
+fn single_match<'a>(document: &'a Value, query: &Query, pointer: &str) -> Result<(Vec<PathElement>, &'a Value)> {
+    let found = engine::locate(document, query)
+        .context(format!("No value at {}", pointer))?
+        .into_iter()
+        .next()
+        .context(format!("No value at {}", pointer))?;
+    Ok((found.path, found.value))
+}
+
+fn parse_pointer(pointer: &str) -> Result<Query> {
+    parser::parse_pointer(pointer)
+        .context(format!("Failed to parse JSON Pointer: {}", pointer))
+}
+
+fn member<'a>(patch_operation: &'a Value, name: &str) -> Result<&'a Value> {
+    patch_operation.get(name)
+        .context(format!("Missing '{}' member", name))
+}
+
+fn member_str<'a>(patch_operation: &'a Value, name: &str) -> Result<&'a str> {
+    member(patch_operation, name)?
+        .as_str()
+        .context(format!("'{}' must be a string", name))
+}
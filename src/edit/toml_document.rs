@@ -0,0 +1,229 @@
+use anyhow::{Context, Result, anyhow};
+use serde_json::Value;
+use toml_edit::{ArrayOfTables, DocumentMut, InlineTable, Item, Table, TableLike};
+use crate::engine::{self, PathElement};
+use super::Operation;
+
+/// Apply operations to TOML source text, keeping comments and formatting intact
+///
+/// Only the values touched by an operation are rewritten; a replaced value
+/// keeps the whitespace and comments that surrounded the old one.
+pub fn apply_to_toml(text: &str, operations: &[Operation]) -> Result<String> {
+    let mut document: DocumentMut = text.parse()
+        .context("Failed to parse TOML input")?;
+
+    for operation in operations {
+        match operation {
+            Operation::Add { path, value } => {
+                let (last, parent_path) = path.split_last()
+                    .context("Cannot add the document root")?;
+                let parent = item_mut(document.as_item_mut(), parent_path)
+                    .context(format!("No value at {}", engine::normalized_path(parent_path)))?;
+                match (last, parent) {
+                    (PathElement::Key(key), parent) => {
+                        let in_table = parent.is_table();
+                        let table = parent.as_table_like_mut()
+                            .context(format!("Cannot add {}: parent is not a table", engine::normalized_path(path)))?;
+                        let item = if in_table { json_to_item(value)? } else { Item::Value(json_to_toml_value(value)?) };
+                        table.insert(key, item);
+                    },
+                    (PathElement::Index(idx), Item::ArrayOfTables(tables)) if *idx <= tables.len() => {
+                        let Value::Object(obj) = value else {
+                            return Err(anyhow!("Cannot add {}: an array of tables can only hold tables", engine::normalized_path(path)));
+                        };
+                        insert_table(tables, *idx, json_to_table(obj)?);
+                    },
+                    (PathElement::Index(idx), parent) => match parent.as_array_mut() {
+                        Some(arr) if *idx <= arr.len() => arr.insert(*idx, json_to_toml_value(value)?),
+                        _ => return Err(anyhow!("Cannot add {}: no array to add it to", engine::normalized_path(path))),
+                    },
+                }
+            },
+            Operation::Replace { path, value } => {
+                if path.is_empty() {
+                    // Replacing the whole document leaves nothing to preserve
+                    let Value::Object(obj) = value else {
+                        return Err(anyhow!("A TOML document must be a table"));
+                    };
+                    *document.as_table_mut() = json_to_table(obj)?;
+                    continue;
+                }
+                let item = item_mut(document.as_item_mut(), path)
+                    .context(format!("No value at {}", engine::normalized_path(path)))?;
+                // A [table] replaced by a plain value becomes a key/value pair of its parent
+                if let Some((PathElement::Key(key), parent_path)) = path.split_last()
+                    && (item.is_table() || item.is_array_of_tables())
+                    && let Item::Value(new_value) = json_to_item(value)?
+                {
+                    let parent = item_mut(document.as_item_mut(), parent_path)
+                        .and_then(Item::as_table_like_mut)
+                        .context(format!("No table at {}", engine::normalized_path(parent_path)))?;
+                    replace_table_with_value(parent, key, new_value);
+                    continue;
+                }
+                replace_item(item, value)
+                    .context(format!("Cannot replace {}", engine::normalized_path(path)))?;
+            },
+            Operation::Remove { path } => {
+                let (last, parent_path) = path.split_last()
+                    .context("Cannot delete the document root")?;
+                let parent = item_mut(document.as_item_mut(), parent_path)
+                    .context(format!("No value at {}", engine::normalized_path(path)))?;
+                let removed = match (last, parent) {
+                    (PathElement::Key(key), parent) => parent.as_table_like_mut()
+                        .and_then(|table| table.remove(key))
+                        .is_some(),
+                    (PathElement::Index(idx), Item::ArrayOfTables(tables)) if *idx < tables.len() => {
+                        tables.remove(*idx);
+                        true
+                    },
+                    (PathElement::Index(idx), parent) => match parent.as_array_mut() {
+                        Some(arr) if *idx < arr.len() => {
+                            arr.remove(*idx);
+                            true
+                        },
+                        _ => false,
+                    },
+                };
+                if !removed {
+                    return Err(anyhow!("No value at {}", engine::normalized_path(path)));
+                }
+            },
+        }
+    }
+
+    Ok(document.to_string())
+}
+
+/// Insert a table into an array of tables, printed where the table it displaces was
+fn insert_table(tables: &mut ArrayOfTables, idx: usize, mut table: Table) {
+    if let Some(position) = tables.get(idx).and_then(Table::position) {
+        table.set_position(position);
+    }
+    let mut all: Vec<Table> = tables.iter().cloned().collect();
+    all.insert(idx, table);
+    *tables = ArrayOfTables::new();
+    for table in all {
+        tables.push(table);
+    }
+}
+
+/// Find the item at a location without inserting anything along the way
+fn item_mut<'a>(item: &'a mut Item, path: &[PathElement]) -> Option<&'a mut Item> {
+    let mut current = item;
+    for element in path {
+        current = match element {
+            PathElement::Key(key) => current.as_table_like_mut()?.get_mut(key)?,
+            PathElement::Index(idx) => current.get_mut(*idx)?,
+        };
+    }
+    Some(current)
+}
+
+/// Replace a [table] or [[array of tables]] with a value, keeping the comments
+/// above its header on the new `key = value` line
+fn replace_table_with_value(parent: &mut dyn TableLike, key: &str, value: toml_edit::Value) {
+    let Some((mut key, item)) = parent.get_key_value_mut(key) else {
+        return;
+    };
+    let header = match &*item {
+        Item::Table(table) => table.decor().prefix().cloned(),
+        Item::ArrayOfTables(tables) => tables.get(0).and_then(|table| table.decor().prefix().cloned()),
+        _ => None,
+    };
+    *item = Item::Value(value);
+
+    // The key was written as a header, so it needs the spacing of a key/value pair
+    let decor = key.leaf_decor_mut();
+    decor.clear();
+    if let Some(header) = header {
+        decor.set_prefix(header);
+    }
+    decor.set_suffix(" ");
+}
+
+/// Replace an item with a new value, keeping its kind and decoration where possible
+fn replace_item(item: &mut Item, value: &Value) -> Result<()> {
+    match (&mut *item, value) {
+        // A [table] stays a table so that its header and position are kept
+        (Item::Table(table), Value::Object(obj)) => {
+            let mut replacement = json_to_table(obj)?;
+            *replacement.decor_mut() = table.decor().clone();
+            if let Some(position) = table.position() {
+                replacement.set_position(position);
+            }
+            *table = replacement;
+        },
+        (Item::Table(_), _) => {
+            *item = Item::Value(json_to_toml_value(value)?);
+        },
+        (Item::ArrayOfTables(_), Value::Array(arr)) if !arr.is_empty() && arr.iter().all(Value::is_object) => {
+            *item = json_to_item(value)?;
+        },
+        (Item::Value(old), _) => {
+            let mut replacement = json_to_toml_value(value)?;
+            *replacement.decor_mut() = old.decor().clone();
+            *old = replacement;
+        },
+        _ => *item = json_to_item(value)?,
+    }
+    Ok(())
+}
+
+/// Convert a JSON value to a TOML item, using tables and arrays of tables for objects
+fn json_to_item(value: &Value) -> Result<Item> {
+    match value {
+        Value::Object(obj) => Ok(Item::Table(json_to_table(obj)?)),
+        Value::Array(arr) if !arr.is_empty() && arr.iter().all(Value::is_object) => {
+            let mut tables = ArrayOfTables::new();
+            for item in arr.iter().filter_map(Value::as_object) {
+                tables.push(json_to_table(item)?);
+            }
+            Ok(Item::ArrayOfTables(tables))
+        },
+        _ => Ok(Item::Value(json_to_toml_value(value)?)),
+    }
+}
+
+/// Convert a JSON object to a standard TOML table
+fn json_to_table(obj: &serde_json::Map<String, Value>) -> Result<Table> {
+    let mut table = Table::new();
+    for (key, value) in obj {
+        table.insert(key, json_to_item(value)?);
+    }
+    // Tables holding only other tables don't need a header of their own
+    table.set_implicit(!table.is_empty());
+    Ok(table)
+}
+
+/// Convert a JSON value to an inline TOML value
+fn json_to_toml_value(value: &Value) -> Result<toml_edit::Value> {
+    match value {
+        Value::Null => Err(anyhow!("TOML cannot represent null")),
+        Value::Bool(b) => Ok((*b).into()),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Ok(i.into())
+            } else if let Some(f) = n.as_f64().filter(|_| !n.is_u64()) {
+                Ok(f.into())
+            } else {
+                Err(anyhow!("TOML cannot represent number {}", n))
+            }
+        },
+        Value::String(s) => Ok(s.clone().into()),
+        Value::Array(arr) => {
+            let mut array = toml_edit::Array::new();
+            for item in arr {
+                array.push(json_to_toml_value(item)?);
+            }
+            Ok(array.into())
+        },
+        Value::Object(obj) => {
+            let mut table = InlineTable::new();
+            for (key, item) in obj {
+                table.insert(key, json_to_toml_value(item)?);
+            }
+            Ok(table.into())
+        },
+    }
+}
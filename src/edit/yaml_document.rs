@@ -0,0 +1,442 @@
+use anyhow::{Context, Result, anyhow};
+use saphyr_parser::{Event, Parser, ScalarStyle, Span, SpannedEventReceiver};
+use serde_json::Value;
+use crate::engine::{self, PathElement};
+use super::Operation;
+
+/// Apply operations to YAML source text, keeping comments and formatting intact
+///
+/// The text is parsed into a tree of nodes that remember where they appear in
+/// the source, and each operation splices only the affected range. Replaced
+/// scalars keep their quoting style when the new value allows it, and
+/// replaced block collections are written back as block collections.
+pub fn apply_to_yaml(text: &str, operations: &[Operation]) -> Result<String> {
+    let mut text = text.to_string();
+    let crlf = text.contains("\r\n");
+
+    // Offsets move with every edit, so the tree is rebuilt for each operation
+    for operation in operations {
+        let root = parse_tree(&text)?;
+        let (range, mut replacement) = match operation {
+            Operation::Add { path, value } => plan_add(&text, &root, path, value)?,
+            Operation::Replace { path, value } => plan_replace(&text, &root, path, value)?,
+            Operation::Remove { path } => plan_remove(&text, &root, path)?,
+        };
+        // New lines are planned with `\n` and take the line ending of the file
+        if crlf {
+            replacement = replacement.replace("\r\n", "\n").replace('\n', "\r\n");
+        }
+        text.replace_range(range, &replacement);
+    }
+
+    Ok(text)
+}
+
+/// A YAML node together with the byte range of its source text
+struct Node {
+    start: usize,
+    end: usize,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    Scalar { value: String, style: ScalarStyle },
+    Mapping { flow: bool, entries: Vec<(Node, Node)> },
+    Sequence { flow: bool, items: Vec<Node> },
+    Alias,
+}
+
+/// Collects parser events along with their source spans
+struct EventCollector<'input> {
+    events: Vec<(Event<'input>, Span)>,
+}
+
+impl<'input> SpannedEventReceiver<'input> for EventCollector<'input> {
+    fn on_event(&mut self, event: Event<'input>, span: Span) {
+        self.events.push((event, span));
+    }
+}
+
+/// Parse the first document of a YAML text into a tree of located nodes
+fn parse_tree(text: &str) -> Result<Node> {
+    let mut collector = EventCollector { events: Vec::new() };
+    Parser::new_from_str(text)
+        .load(&mut collector, false)
+        .map_err(|e| anyhow!("Failed to parse YAML input: {}", e))?;
+
+    // Markers count characters, splicing needs byte offsets
+    let offsets: Vec<usize> = text.char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(text.len()))
+        .collect();
+
+    let mut pos = collector.events.iter()
+        .position(|(event, _)| matches!(event, Event::DocumentStart(_)))
+        .context("YAML input contains no document")? + 1;
+    build_node(text, &collector.events, &offsets, &mut pos)
+}
+
+/// Build the node starting at `events[*pos]`, advancing past its last event
+fn build_node(text: &str, events: &[(Event, Span)], offsets: &[usize], pos: &mut usize) -> Result<Node> {
+    let (event, span) = events.get(*pos).context("Unexpected end of YAML input")?;
+    *pos += 1;
+    let start = offsets[span.start.index()];
+    let end = offsets[span.end.index()];
+
+    match event {
+        Event::Scalar(value, style, _, _) => {
+            let (start, end) = match style {
+                // Block scalars start at their indicator and end with their last
+                // line of content rather than at the next token
+                ScalarStyle::Literal | ScalarStyle::Folded => {
+                    let content_end = start + text[start..end].trim_end().len();
+                    (text[..start].rfind(['|', '>']).unwrap_or(start), content_end)
+                },
+                // Quoted scalars can run on to the next token, so end them at the closing quote
+                ScalarStyle::SingleQuoted | ScalarStyle::DoubleQuoted => (start, closing_quote(text, start).unwrap_or(end)),
+                _ => (start, end),
+            };
+            Ok(Node { start, end, kind: NodeKind::Scalar { value: value.to_string(), style: *style } })
+        },
+        Event::Alias(_) => Ok(Node { start, end, kind: NodeKind::Alias }),
+        Event::MappingStart(..) => {
+            // Block collections start with an empty span, flow ones at their bracket
+            let flow = start != end;
+            let mut entries = Vec::new();
+            while !matches!(events.get(*pos), Some((Event::MappingEnd, _)) | None) {
+                let key = build_node(text, events, offsets, pos)?;
+                let value = build_node(text, events, offsets, pos)?;
+                entries.push((key, value));
+            }
+            let (_, end_span) = events.get(*pos).context("Unexpected end of YAML input")?;
+            *pos += 1;
+            let end = if flow {
+                offsets[end_span.end.index()]
+            } else {
+                entries.last().map_or(start, |(_, value)| value.end)
+            };
+            Ok(Node { start, end, kind: NodeKind::Mapping { flow, entries } })
+        },
+        Event::SequenceStart(..) => {
+            let flow = start != end;
+            let mut items = Vec::new();
+            while !matches!(events.get(*pos), Some((Event::SequenceEnd, _)) | None) {
+                items.push(build_node(text, events, offsets, pos)?);
+            }
+            let (_, end_span) = events.get(*pos).context("Unexpected end of YAML input")?;
+            *pos += 1;
+            let end = if flow {
+                offsets[end_span.end.index()]
+            } else {
+                items.last().map_or(start, |item| item.end)
+            };
+            Ok(Node { start, end, kind: NodeKind::Sequence { flow, items } })
+        },
+        _ => Err(anyhow!("Unexpected YAML event: {:?}", event)),
+    }
+}
+
+/// Byte offset just past the closing quote of the quoted scalar starting at `start`
+fn closing_quote(text: &str, start: usize) -> Option<usize> {
+    let quote = text[start..].chars().next()?;
+    let mut chars = text[start + 1..].char_indices();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            // Backslash escapes only exist in double-quoted scalars
+            '\\' if quote == '"' => { chars.next(); },
+            // A doubled single quote is an escaped quote
+            '\'' if quote == '\'' && text[start + 1 + offset + 1..].starts_with('\'') => { chars.next(); },
+            c if c == quote => return Some(start + 1 + offset + 1),
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Find the node at a location
+fn find<'a>(node: &'a Node, path: &[PathElement]) -> Result<&'a Node> {
+    let mut current = node;
+    for (depth, element) in path.iter().enumerate() {
+        current = match (element, &current.kind) {
+            (PathElement::Key(key), NodeKind::Mapping { entries, .. }) => entries.iter()
+                .find(|(k, _)| matches!(&k.kind, NodeKind::Scalar { value, .. } if value == key))
+                .map(|(_, value)| value),
+            (PathElement::Index(idx), NodeKind::Sequence { items, .. }) => items.get(*idx),
+            (_, NodeKind::Alias) => return Err(anyhow!(
+                "Cannot edit through the alias at {}", engine::normalized_path(&path[..depth])
+            )),
+            _ => None,
+        }.context(format!("No value at {}", engine::normalized_path(&path[..=depth])))?;
+    }
+    Ok(current)
+}
+
+/// Plan the edit inserting a new mapping entry or sequence item
+fn plan_add(text: &str, root: &Node, path: &[PathElement], value: &Value) -> Result<(std::ops::Range<usize>, String)> {
+    let (last, parent_path) = path.split_last()
+        .context("Cannot add the document root")?;
+    let parent = find(root, parent_path)?;
+    match (last, &parent.kind) {
+        (PathElement::Key(key), NodeKind::Mapping { flow, entries }) => add_entry(text, parent, *flow, entries, key, value),
+        (PathElement::Index(idx), NodeKind::Sequence { flow, items }) if *idx <= items.len() => {
+            add_item(text, parent, *flow, items, *idx, value)
+        },
+        _ => Err(anyhow!("Cannot add {}: no mapping or sequence to add it to", engine::normalized_path(path))),
+    }
+}
+
+/// Plan the edit appending a new key to a mapping
+fn add_entry(text: &str, parent: &Node, flow: bool, entries: &[(Node, Node)], key: &str, value: &Value) -> Result<(std::ops::Range<usize>, String)> {
+    if flow {
+        let entry = format!("{}: {}", render_key(key), render_inline(value));
+        return Ok(match entries.last() {
+            Some((_, last)) => (last.end..last.end, format!(", {}", entry)),
+            None => (parent.start + 1..parent.start + 1, entry),
+        });
+    }
+
+    // Append the new key on its own line after the last entry, at the same indentation
+    let (first_key, _) = entries.first().context("Empty block mapping")?;
+    let indent = " ".repeat(column(text, first_key.start));
+    let last_end = entries.last().map_or(parent.end, |(_, value)| value.end);
+    let (insert_at, prefix) = next_line(text, last_end);
+    let entry = if is_empty_collection(value) || !(value.is_object() || value.is_array()) {
+        format!("{}: {}", render_key(key), render_inline(value))
+    } else {
+        format!("{}:\n{}", render_key(key), render_block(value, indent.len() + 2, true))
+    };
+    Ok((insert_at..insert_at, format!("{}{}{}\n", prefix, indent, entry)))
+}
+
+/// Plan the edit inserting a new item into a sequence before `idx`, or at its end
+fn add_item(text: &str, parent: &Node, flow: bool, items: &[Node], idx: usize, value: &Value) -> Result<(std::ops::Range<usize>, String)> {
+    if flow {
+        let item = render_inline(value);
+        return Ok(match (items.get(idx), items.last()) {
+            (Some(next), _) => (next.start..next.start, format!("{}, ", item)),
+            (None, Some(last)) => (last.end..last.end, format!(", {}", item)),
+            (None, None) => (parent.start + 1..parent.start + 1, item),
+        });
+    }
+
+    // New items go on their own line, at the indentation of the existing dashes
+    let dash = |item: &Node| text[..item.start].rfind('-').unwrap_or(item.start);
+    let first = items.first().context("Empty block sequence")?;
+    let indent = column(text, dash(first));
+    let item = if is_empty_collection(value) || !(value.is_object() || value.is_array()) {
+        format!("- {}", render_inline(value))
+    } else {
+        format!("- {}", render_block(value, indent + 2, false))
+    };
+    let (insert_at, prefix) = match items.get(idx) {
+        Some(next) => {
+            let start = dash(next);
+            let line_start = text[..start].rfind('\n').map_or(0, |offset| offset + 1);
+            if !text[line_start..start].trim().is_empty() {
+                return Err(anyhow!("Cannot insert before a sequence item that does not start its line"));
+            }
+            (line_start, "")
+        },
+        None => next_line(text, items.last().map_or(parent.end, |item| item.end)),
+    };
+    Ok((insert_at..insert_at, format!("{}{}{}\n", prefix, " ".repeat(indent), item)))
+}
+
+/// Offset of the line following the one containing `offset`, and the line break
+/// to write first when that line is the last one and has no line break
+fn next_line(text: &str, offset: usize) -> (usize, &'static str) {
+    match text[offset..].find('\n') {
+        Some(line_end) => (offset + line_end + 1, ""),
+        None => (text.len(), "\n"),
+    }
+}
+
+/// Plan the edit replacing an existing node
+fn plan_replace(text: &str, root: &Node, path: &[PathElement], value: &Value) -> Result<(std::ops::Range<usize>, String)> {
+    let node = find(root, path)?;
+
+    // A block sequence starts at the dash of its first item, not at the item's content
+    let (start, block) = match &node.kind {
+        NodeKind::Sequence { flow: false, items } => {
+            let start = items.first().map_or(node.start, |item| text[..item.start].rfind('-').unwrap_or(item.start));
+            (start, true)
+        },
+        NodeKind::Mapping { flow: false, .. } => (node.start, true),
+        _ => (node.start, false),
+    };
+
+    let replacement = match (&node.kind, value) {
+        // Keep block collections in block style, aligned with the old content
+        (_, Value::Object(_) | Value::Array(_)) if block && !is_empty_collection(value) => {
+            render_block(value, column(text, start), false)
+        },
+        // Any other value replacing a block collection moves up onto the line of its key
+        _ if block && text[..start].trim_end().ends_with(':') => {
+            return Ok((text[..start].trim_end().len()..node.end, format!(" {}", render_inline(value))));
+        },
+        (NodeKind::Scalar { style, .. }, Value::String(s)) => render_string(s, *style),
+        _ => render_inline(value),
+    };
+    Ok((start..node.end, replacement))
+}
+
+/// Plan the edit removing a mapping entry or sequence item
+fn plan_remove(text: &str, root: &Node, path: &[PathElement]) -> Result<(std::ops::Range<usize>, String)> {
+    let (last, parent_path) = path.split_last()
+        .context("Cannot delete the document root")?;
+    let parent = find(root, parent_path)?;
+
+    // Source ranges of every entry of the parent, and the position of the one to remove
+    let (flow, ranges, idx, empty) = match (&parent.kind, last) {
+        (NodeKind::Mapping { flow, entries }, PathElement::Key(key)) => {
+            let idx = entries.iter()
+                .position(|(k, _)| matches!(&k.kind, NodeKind::Scalar { value, .. } if value == key));
+            let ranges: Vec<(usize, usize)> = entries.iter().map(|(k, v)| (k.start, v.end)).collect();
+            (*flow, ranges, idx, "{}")
+        },
+        (NodeKind::Sequence { flow, items }, PathElement::Index(idx)) => {
+            let ranges: Vec<(usize, usize)> = items.iter()
+                .map(|item| {
+                    // A block item starts at its dash
+                    let start = if *flow { item.start } else { text[..item.start].rfind('-').unwrap_or(item.start) };
+                    (start, item.end)
+                })
+                .collect();
+            (*flow, ranges, Some(*idx).filter(|i| *i < items.len()), "[]")
+        },
+        _ => (false, Vec::new(), None, ""),
+    };
+    let idx = idx.context(format!("No value at {}", engine::normalized_path(path)))?;
+    let (start, end) = ranges[idx];
+
+    // Removing the only entry of a block collection leaves an empty flow collection,
+    // moved up onto the line of its key
+    if ranges.len() == 1 {
+        if flow {
+            return Ok((start..end, String::new()));
+        }
+        let before = text[..parent.start].trim_end();
+        return Ok(if before.ends_with(':') {
+            (before.len()..parent.end, format!(" {}", empty))
+        } else {
+            (parent.start..parent.end, empty.to_string())
+        });
+    }
+
+    if !flow {
+        // Remove whole lines when the entry starts its line
+        let line_start = text[..start].rfind('\n').map_or(0, |offset| offset + 1);
+        if text[line_start..start].trim().is_empty() {
+            let line_end = text[end..].find('\n').map_or(text.len(), |offset| end + offset + 1);
+            return Ok((line_start..line_end, String::new()));
+        }
+    }
+
+    // Otherwise remove up to the next entry, or from the end of the previous one
+    Ok(match ranges.get(idx + 1) {
+        Some((next_start, _)) => (start..*next_start, String::new()),
+        None => (ranges[idx - 1].1..end, String::new()),
+    })
+}
+
+/// Number of characters between the start of the line and a byte offset
+fn column(text: &str, offset: usize) -> usize {
+    let line_start = text[..offset].rfind('\n').map_or(0, |o| o + 1);
+    text[line_start..offset].chars().count()
+}
+
+fn is_empty_collection(value: &Value) -> bool {
+    match value {
+        Value::Object(obj) => obj.is_empty(),
+        Value::Array(arr) => arr.is_empty(),
+        _ => false,
+    }
+}
+
+/// Render a value in block style, indenting every line but the first by `indent`
+/// (or every line when `indent_first` is set)
+fn render_block(value: &Value, indent: usize, indent_first: bool) -> String {
+    let padding = " ".repeat(indent);
+    let body = block_lines(value).join(&format!("\n{}", padding));
+    if indent_first { format!("{}{}", padding, body) } else { body }
+}
+
+/// The lines of a value in block style, with scalars quoted as in `render_string`
+///
+/// Mapping values are indented under their key and sequences under a key are
+/// not, as serde_yaml writes them.
+fn block_lines(value: &Value) -> Vec<String> {
+    let is_block = |value: &Value| (value.is_object() || value.is_array()) && !is_empty_collection(value);
+    match value {
+        Value::Object(obj) if is_block(value) => obj.iter()
+            .flat_map(|(key, item)| match item {
+                Value::Object(_) if is_block(item) => std::iter::once(format!("{}:", render_key(key)))
+                    .chain(block_lines(item).into_iter().map(|line| format!("  {}", line)))
+                    .collect(),
+                Value::Array(_) if is_block(item) => std::iter::once(format!("{}:", render_key(key)))
+                    .chain(block_lines(item))
+                    .collect(),
+                _ => vec![format!("{}: {}", render_key(key), render_inline(item))],
+            })
+            .collect(),
+        Value::Array(arr) if is_block(value) => arr.iter()
+            .flat_map(|item| {
+                let lines = block_lines(item);
+                let rest = lines[1..].iter().map(|line| format!("  {}", line));
+                std::iter::once(format!("- {}", lines[0])).chain(rest).collect::<Vec<_>>()
+            })
+            .collect(),
+        _ => vec![render_inline(value)],
+    }
+}
+
+/// Render a value on a single line using flow style
+fn render_inline(value: &Value) -> String {
+    match value {
+        Value::String(s) => render_string(s, ScalarStyle::Plain),
+        Value::Array(arr) => {
+            let items: Vec<String> = arr.iter().map(render_inline).collect();
+            format!("[{}]", items.join(", "))
+        },
+        Value::Object(obj) => {
+            let entries: Vec<String> = obj.iter()
+                .map(|(key, item)| format!("{}: {}", render_key(key), render_inline(item)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        },
+        _ => value.to_string(),
+    }
+}
+
+fn render_key(key: &str) -> String {
+    render_string(key, ScalarStyle::Plain)
+}
+
+/// Render a string in the requested style, falling back to double quotes
+/// whenever the style cannot represent it unambiguously
+fn render_string(s: &str, style: ScalarStyle) -> String {
+    match style {
+        ScalarStyle::Plain | ScalarStyle::Literal | ScalarStyle::Folded if is_plain_safe(s) => s.to_string(),
+        ScalarStyle::SingleQuoted if !s.contains(['\n', '\r']) => format!("'{}'", s.replace('\'', "''")),
+        _ => Value::String(s.to_string()).to_string(),
+    }
+}
+
+/// Whether a string can be written unquoted and still read back as the same string
+fn is_plain_safe(s: &str) -> bool {
+    if s.is_empty() || s.trim() != s || s.contains(['\n', '\r', '\t', ',', '[', ']', '{', '}']) {
+        return false;
+    }
+    if s.starts_with(['-', '?', ':', '#', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`'])
+        || s.contains(": ") || s.contains(" #") || s.ends_with(':')
+    {
+        return false;
+    }
+    // YAML 1.1 readers such as PyYAML and go-yaml v2 also take these as booleans or null
+    const YAML_1_1_KEYWORDS: [&str; 10] = ["y", "n", "yes", "no", "on", "off", "true", "false", "null", "~"];
+    if YAML_1_1_KEYWORDS.iter().any(|keyword| s.eq_ignore_ascii_case(keyword)) {
+        return false;
+    }
+    // Rule out strings that YAML would read as numbers, booleans or null
+    matches!(serde_yaml::from_str::<Value>(s), Ok(Value::String(parsed)) if parsed == s)
+}
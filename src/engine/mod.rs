@@ -3,59 +3,163 @@ use serde_json::Value;
 use crate::parser::{Query, PathSegment, FilterExpression, ComparisonOperator, LiteralValue};
 
 /// Apply a query to a JSON value and return the resulting JSON
+///
+/// Queries made only of field and index accesses return the matched value
+/// itself; any other query returns the list of matches as an array.
 pub fn apply_query(json: &Value, query: &Query) -> Result<Value> {
+    let nodes = select(json, query)?;
+
+    if query.is_singular() {
+        // A singular query either matches exactly one node or fails
+        nodes.first()
+            .map(|node| (*node).clone())
+            .context("Query did not match any value")
+    } else {
+        Ok(Value::Array(nodes.into_iter().cloned().collect()))
+    }
+}
+
+/// A single step in the location of a node within a document
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PathElement {
+    Key(String),
+    Index(usize),
+}
+
+/// A node matched by a query together with its location in the document
+#[derive(Debug, Clone)]
+pub struct Match<'a> {
+    pub path: Vec<PathElement>,
+    pub value: &'a Value,
+}
+
+/// Apply a query to a JSON value and return every matched node, in document order
+pub fn select<'a>(json: &'a Value, query: &Query) -> Result<Vec<&'a Value>> {
+    Ok(locate(json, query)?.into_iter().map(|m| m.value).collect())
+}
+
+/// Apply a query to a JSON value and return every matched node along with its location
+pub fn locate<'a>(json: &'a Value, query: &Query) -> Result<Vec<Match<'a>>> {
     // Start with the root JSON value
-    let mut result = json.clone();
-    
-    // Apply each path segment in the main path
+    let mut nodes = vec![Match { path: Vec::new(), value: json }];
+    let mut singular = true;
+
+    // Apply each path segment in the main path to every current node
     for segment in &query.path_segments {
-        result = apply_path_segment(&result, segment)?;
+        let mut next = Vec::new();
+        for node in nodes {
+            match select_segment(node.value, segment) {
+                Ok(found) => {
+                    next.extend(found.into_iter().map(|(step, value)| {
+                        let mut path = node.path.clone();
+                        path.push(step);
+                        Match { path, value }
+                    }));
+                },
+                // While the path is singular a segment that does not apply is an error,
+                // once it has fanned out, nodes it does not apply to are skipped
+                Err(e) if singular => return Err(e),
+                Err(_) => {}
+            }
+        }
+        singular &= segment.is_singular();
+        nodes = next;
     }
-    
+
     // Apply recursive paths if any
     if !query.recursive_paths.is_empty() {
         let mut recursive_results = Vec::new();
-        
+
         // For each recursive path defined
         for recursive_path in &query.recursive_paths {
-            // Apply recursive search starting from current result
-            collect_recursive(&result, recursive_path, &mut recursive_results)?;
+            // Apply recursive search starting from every current node
+            for node in &nodes {
+                let mut path = node.path.clone();
+                collect_recursive(node.value, recursive_path, &mut path, &mut recursive_results)?;
+            }
         }
-        
-        // If we found any results, replace the current result with the collection
-        if !recursive_results.is_empty() {
-            result = Value::Array(recursive_results);
+
+        nodes = recursive_results;
+    }
+
+    Ok(nodes)
+}
+
+/// Format a location as an RFC 9535 normalized path, e.g. `$['store']['book'][2]`
+pub fn normalized_path(path: &[PathElement]) -> String {
+    let mut out = String::from("$");
+    for element in path {
+        match element {
+            PathElement::Index(idx) => out.push_str(&format!("[{}]", idx)),
+            PathElement::Key(key) => {
+                out.push_str("['");
+                for c in key.chars() {
+                    match c {
+                        '\'' => out.push_str("\\'"),
+                        '\\' => out.push_str("\\\\"),
+                        '\u{08}' => out.push_str("\\b"),
+                        '\u{0C}' => out.push_str("\\f"),
+                        '\n' => out.push_str("\\n"),
+                        '\r' => out.push_str("\\r"),
+                        '\t' => out.push_str("\\t"),
+                        c if c < '\u{20}' => out.push_str(&format!("\\u{:04x}", c as u32)),
+                        c => out.push(c),
+                    }
+                }
+                out.push_str("']");
+            },
         }
     }
-    
-    Ok(result)
+    out
+}
+
+/// Format a location as an RFC 6901 JSON Pointer, e.g. `/store/book/2`
+pub fn json_pointer(path: &[PathElement]) -> String {
+    let mut out = String::new();
+    for element in path {
+        out.push('/');
+        match element {
+            PathElement::Index(idx) => out.push_str(&idx.to_string()),
+            PathElement::Key(key) => out.push_str(&key.replace('~', "~0").replace('/', "~1")),
+        }
+    }
+    out
 }
 
 /// Recursively collect values that match a given path pattern
-fn collect_recursive(json: &Value, path_segments: &[PathSegment], results: &mut Vec<Value>) -> Result<()> {
+fn collect_recursive<'a>(
+    json: &'a Value,
+    path_segments: &[PathSegment],
+    path: &mut Vec<PathElement>,
+    results: &mut Vec<Match<'a>>,
+) -> Result<()> {
     match json {
         Value::Object(obj) => {
             // For each property in the object
             for (key, value) in obj {
+                path.push(PathElement::Key(key.clone()));
+
                 // If this property matches the field we're looking for
-                if path_segments.len() == 1 {
-                    if let PathSegment::Field(field_name) = &path_segments[0] {
-                        // Check if this key matches the field name
-                        if key == field_name {
-                            results.push(value.clone());
-                        }
-                    }
+                if path_segments.len() == 1
+                    && let PathSegment::Field(field_name) = &path_segments[0]
+                    // Check if this key matches the field name
+                    && key == field_name
+                {
+                    results.push(Match { path: path.clone(), value });
                 }
                 
                 // Then recursively search this property
-                collect_recursive(value, path_segments, results)?;
+                collect_recursive(value, path_segments, path, results)?;
+                path.pop();
             }
         },
         Value::Array(arr) => {
             // For each item in the array
-            for item in arr {
+            for (idx, item) in arr.iter().enumerate() {
                 // Recursively search this item
-                collect_recursive(item, path_segments, results)?;
+                path.push(PathElement::Index(idx));
+                collect_recursive(item, path_segments, path, results)?;
+                path.pop();
             }
         },
         _ => {}
@@ -64,13 +168,33 @@ fn collect_recursive(json: &Value, path_segments: &[PathSegment], results: &mut
     Ok(())
 }
 
-/// Apply a single path segment to a JSON value
-fn apply_path_segment(json: &Value, segment: &PathSegment) -> Result<Value> {
+/// Resolve a possibly negative index against an array length
+fn resolve_index(len: usize, idx: i64) -> Option<usize> {
+    if idx < 0 {
+        // Handle negative indices (counting from the end)
+        usize::try_from(len as i64 + idx).ok()
+    } else {
+        usize::try_from(idx).ok()
+    }
+}
+
+/// Parse a JSON Pointer token as an array index
+///
+/// Array indices are plain decimal numbers without leading zeros.
+pub fn pointer_index(token: &str) -> Option<usize> {
+    let is_index = token == "0"
+        || (!token.starts_with('0') && !token.is_empty() && token.chars().all(|c| c.is_ascii_digit()));
+    token.parse::<usize>().ok().filter(|_| is_index)
+}
+
+/// Apply a single path segment to a JSON value and return the selected nodes
+/// along with the step leading to each of them
+fn select_segment<'a>(json: &'a Value, segment: &PathSegment) -> Result<Vec<(PathElement, &'a Value)>> {
     match segment {
         PathSegment::Field(name) => {
             if let Value::Object(obj) = json {
                 obj.get(name)
-                   .cloned()
+                   .map(|value| vec![(PathElement::Key(name.clone()), value)])
                    .context(format!("Field '{}' not found", name))
             } else {
                 Err(anyhow::anyhow!("Cannot access field on non-object value"))
@@ -78,39 +202,20 @@ fn apply_path_segment(json: &Value, segment: &PathSegment) -> Result<Value> {
         },
         PathSegment::Index(idx) => {
             if let Value::Array(arr) = json {
-                let idx = if *idx < 0 {
-                    // Handle negative indices (counting from the end)
-                    (arr.len() as i64 + idx) as usize
-                } else {
-                    *idx as usize
-                };
-                
-                arr.get(idx)
-                   .cloned()
+                resolve_index(arr.len(), *idx)
+                   .and_then(|i| arr.get(i).map(|value| vec![(PathElement::Index(i), value)]))
                    .context(format!("Index {} out of bounds", idx))
             } else {
                 Err(anyhow::anyhow!("Cannot access index on non-array value"))
             }
         },
         PathSegment::MultiIndex(indices) => {
-            // Create a new array with the selected indices
+            // Select each index that exists, in the order given
             if let Value::Array(arr) = json {
-                let mut result = Vec::new();
-                
-                for &idx in indices {
-                    let usize_idx = if idx < 0 {
-                        // Handle negative indices (counting from the end)
-                        (arr.len() as i64 + idx) as usize
-                    } else {
-                        idx as usize
-                    };
-                    
-                    if let Some(value) = arr.get(usize_idx) {
-                        result.push(value.clone());
-                    }
-                }
-                
-                Ok(Value::Array(result))
+                Ok(indices.iter()
+                    .filter_map(|&idx| resolve_index(arr.len(), idx))
+                    .filter_map(|i| arr.get(i).map(|value| (PathElement::Index(i), value)))
+                    .collect())
             } else {
                 Err(anyhow::anyhow!("Cannot access indices on non-array value"))
             }
@@ -120,28 +225,41 @@ fn apply_path_segment(json: &Value, segment: &PathSegment) -> Result<Value> {
             match json {
                 Value::Array(arr) => {
                     let mut result = Vec::new();
-                    for item in arr {
+                    for (idx, item) in arr.iter().enumerate() {
                         if evaluate_filter(item, filter_expr)? {
-                            result.push(item.clone());
+                            result.push((PathElement::Index(idx), item));
                         }
                     }
-                    Ok(Value::Array(result))
+                    Ok(result)
                 },
                 _ => Err(anyhow::anyhow!("Cannot filter non-array value"))
             }
         },
-        PathSegment::RecursiveWildcard => {
-            // For recursive wildcard [*], we need to collect all elements in an array
+        PathSegment::Token(token) => {
             match json {
+                Value::Object(obj) => obj.get(token)
+                    .map(|value| vec![(PathElement::Key(token.clone()), value)])
+                    .context(format!("Field '{}' not found", token)),
                 Value::Array(arr) => {
-                    // Return a copy of the entire array
-                    Ok(Value::Array(arr.clone()))
-                },
-                Value::Object(obj) => {
-                    // Collect all field values into an array
-                    let values: Vec<Value> = obj.values().cloned().collect();
-                    Ok(Value::Array(values))
+                    let idx = pointer_index(token)
+                        .context(format!("Invalid array index '{}'", token))?;
+                    arr.get(idx)
+                        .map(|value| vec![(PathElement::Index(idx), value)])
+                        .context(format!("Index {} out of bounds", idx))
                 },
+                _ => Err(anyhow::anyhow!("Cannot access '{}' on primitive value", token)),
+            }
+        },
+        PathSegment::RecursiveWildcard => {
+            // For recursive wildcard [*], select every element or field value
+            match json {
+                Value::Array(arr) => Ok(arr.iter()
+                    .enumerate()
+                    .map(|(idx, item)| (PathElement::Index(idx), item))
+                    .collect()),
+                Value::Object(obj) => Ok(obj.iter()
+                    .map(|(key, item)| (PathElement::Key(key.clone()), item))
+                    .collect()),
                 _ => Err(anyhow::anyhow!("Cannot apply wildcard to primitive value"))
             }
         }
@@ -151,13 +269,13 @@ fn apply_path_segment(json: &Value, segment: &PathSegment) -> Result<Value> {
 /// Evaluate a filter expression against a JSON value
 fn evaluate_filter(json: &Value, filter: &FilterExpression) -> Result<bool> {
     // Extract the value at the path specified in the filter
-    let mut current = json.clone();
+    let mut current = json;
     
     // Try to apply each path segment
     for segment in &filter.path {
-        match apply_path_segment(&current, segment) {
-            Ok(value) => current = value,
-            Err(_) => {
+        match select_segment(current, segment) {
+            Ok(found) if found.len() == 1 => current = found[0].1,
+            _ => {
                 // Field doesn't exist - when checking for null equality,
                 // missing fields should NOT be treated the same as explicit nulls
                 return Ok(false);
@@ -166,7 +284,7 @@ fn evaluate_filter(json: &Value, filter: &FilterExpression) -> Result<bool> {
     }
     
     // Compare the value with the filter literal
-    match (&current, &filter.operator, &filter.value) {
+    match (current, &filter.operator, &filter.value) {
         // String comparisons
         (Value::String(s), ComparisonOperator::Equal, LiteralValue::String(val)) => Ok(s == val),
         (Value::String(s), ComparisonOperator::NotEqual, LiteralValue::String(val)) => Ok(s != val),
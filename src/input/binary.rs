@@ -0,0 +1,105 @@
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde_json::{Map, Number, Value, json};
+use super::InputFormat;
+
+/// Decode a MessagePack document, which must be a single value
+///
+/// Binary blobs become base64 strings and extension values become
+/// `{"type", "data"}` objects with base64 data. Floats that JSON cannot
+/// represent (NaN and infinities) become null.
+pub fn parse_msgpack(content: &[u8]) -> Result<Value> {
+    let mut reader = content;
+    let value = rmpv::decode::read_value(&mut reader)
+        .context("Failed to parse MessagePack input")?;
+    if !reader.is_empty() {
+        return Err(anyhow!("Failed to parse MessagePack input: {} bytes follow the document", reader.len()));
+    }
+    Ok(msgpack_to_json(value))
+}
+
+fn msgpack_to_json(value: rmpv::Value) -> Value {
+    match value {
+        rmpv::Value::Nil => Value::Null,
+        rmpv::Value::Boolean(b) => Value::Bool(b),
+        rmpv::Value::Integer(i) => i.as_i64().map(Value::from)
+            .or_else(|| i.as_u64().map(Value::from))
+            .unwrap_or(Value::Null),
+        rmpv::Value::F32(f) => float(f64::from(f)),
+        rmpv::Value::F64(f) => float(f),
+        // MessagePack strings are not guaranteed to be valid UTF-8
+        rmpv::Value::String(s) => Value::String(String::from_utf8_lossy(s.as_bytes()).into_owned()),
+        rmpv::Value::Binary(bytes) => Value::String(BASE64.encode(bytes)),
+        rmpv::Value::Array(items) => Value::Array(items.into_iter().map(msgpack_to_json).collect()),
+        rmpv::Value::Map(entries) => object(entries.into_iter().map(|(key, value)| (msgpack_to_json(key), msgpack_to_json(value)))),
+        rmpv::Value::Ext(kind, data) => json!({"type": kind, "data": BASE64.encode(data)}),
+    }
+}
+
+/// Decode a CBOR document, which must be a single data item
+///
+/// Byte strings become base64 strings, tags are dropped in favour of the
+/// value they tag, and integers too large for 64 bits become floats. Floats
+/// that JSON cannot represent (NaN and infinities) become null.
+pub fn parse_cbor(content: &[u8]) -> Result<Value> {
+    let mut reader = content;
+    let value: ciborium::Value = ciborium::from_reader(&mut reader)
+        .context("Failed to parse CBOR input")?;
+    if !reader.is_empty() {
+        return Err(anyhow!("Failed to parse CBOR input: {} bytes follow the document", reader.len()));
+    }
+    Ok(cbor_to_json(value))
+}
+
+fn cbor_to_json(value: ciborium::Value) -> Value {
+    match value {
+        ciborium::Value::Integer(i) => {
+            let i = i128::from(i);
+            i64::try_from(i).map(Value::from)
+                .or_else(|_| u64::try_from(i).map(Value::from))
+                .unwrap_or_else(|_| float(i as f64))
+        },
+        ciborium::Value::Bytes(bytes) => Value::String(BASE64.encode(bytes)),
+        ciborium::Value::Float(f) => float(f),
+        ciborium::Value::Text(s) => Value::String(s),
+        ciborium::Value::Bool(b) => Value::Bool(b),
+        ciborium::Value::Tag(_, value) => cbor_to_json(*value),
+        ciborium::Value::Array(items) => Value::Array(items.into_iter().map(cbor_to_json).collect()),
+        ciborium::Value::Map(entries) => object(entries.into_iter().map(|(key, value)| (cbor_to_json(key), cbor_to_json(value)))),
+        _ => Value::Null,
+    }
+}
+
+fn float(f: f64) -> Value {
+    Number::from_f64(f).map_or(Value::Null, Value::Number)
+}
+
+/// Build an object from map entries whose keys may be any value
+///
+/// Strings are used as they are and other keys as their compact JSON text,
+/// so the integer key `1` becomes `"1"`. A later entry replaces an earlier
+/// one that ends up with the same key.
+fn object(entries: impl Iterator<Item = (Value, Value)>) -> Value {
+    let obj: Map<String, Value> = entries
+        .map(|(key, value)| match key {
+            Value::String(key) => (key, value),
+            key => (key.to_string(), value),
+        })
+        .collect();
+    Value::Object(obj)
+}
+
+/// Guess whether binary content is MessagePack or CBOR by decoding it as both
+pub fn sniff_binary(content: &[u8]) -> Result<InputFormat> {
+    // The self-described CBOR tag 55799 exists to make CBOR recognisable
+    if content.starts_with(&[0xd9, 0xd9, 0xf7]) {
+        return Ok(InputFormat::Cbor);
+    }
+    match (parse_msgpack(content).is_ok(), parse_cbor(content).is_ok()) {
+        (true, false) => Ok(InputFormat::Msgpack),
+        (false, true) => Ok(InputFormat::Cbor),
+        (true, true) => Err(anyhow!("Cannot detect the input format: it is valid as both MessagePack and CBOR; use -i/--input-format")),
+        (false, false) => Err(anyhow!("Cannot detect the input format of binary input; use -i/--input-format")),
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Map, Value};
+
+/// Map an INI file to an object of sections
+///
+/// Keys before the first section header are members of the top-level object,
+/// and every `[section]` becomes an object of its keys. Keys and values are
+/// separated by `=` or `:`, and a key without a value, as in my.cnf's
+/// `skip-networking`, maps to null. Lines starting with `;` or `#` are
+/// comments. Repeated sections are combined, and a repeated key keeps its last
+/// value. Quotes around a value are removed, and backslashes are kept as
+/// written, so Windows paths read as expected.
+pub fn parse_ini(text: &str, expand_keys: bool) -> Result<Value> {
+    let mut obj = Map::new();
+    let mut section: Vec<String> = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        if line.is_empty() || line.starts_with([';', '#']) {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header.strip_suffix(']')
+                .context(format!("Failed to parse INI input at line {}: unclosed section header", idx + 1))?;
+            section = key_path(name.trim(), expand_keys);
+            continue;
+        }
+
+        let (key, value) = match line.find(['=', ':']) {
+            Some(separator) => {
+                let value = line[separator + 1..].trim();
+                (&line[..separator], Value::String(unquote(value).to_string()))
+            },
+            None => (line, Value::Null),
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(anyhow!("Failed to parse INI input at line {}: missing key", idx + 1));
+        }
+        let mut path = section.clone();
+        path.extend(key_path(key, expand_keys));
+        insert(&mut obj, &path, value)?;
+    }
+    Ok(Value::Object(obj))
+}
+
+/// Remove one pair of matching quotes around a value
+fn unquote(value: &str) -> &str {
+    ['"', '\'']
+        .into_iter()
+        .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(value)
+}
+
+/// Map a Java `.properties` file to an object
+///
+/// Follows `java.util.Properties`: `#` and `!` start comments, keys are
+/// separated from values by `=`, `:` or whitespace, a trailing backslash
+/// continues a line, and `\t`, `\n` and `\uXXXX` escapes are decoded.
+pub fn parse_properties(text: &str, expand_keys: bool) -> Result<Value> {
+    let mut obj = Map::new();
+    let mut lines = text.lines().enumerate();
+    while let Some((idx, line)) = lines.next() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with(['#', '!']) {
+            continue;
+        }
+
+        // An odd number of trailing backslashes joins the next line, minus its indentation
+        let mut logical = line.to_string();
+        while ends_with_continuation(&logical) {
+            logical.pop();
+            match lines.next() {
+                Some((_, next)) => logical.push_str(next.trim_start()),
+                None => break,
+            }
+        }
+
+        let (key, value) = property(&logical)
+            .context(format!("Failed to parse properties input at line {}", idx + 1))?;
+        insert(&mut obj, &key_path(&key, expand_keys), Value::String(value))?;
+    }
+    Ok(Value::Object(obj))
+}
+
+fn ends_with_continuation(line: &str) -> bool {
+    line.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+/// Split a logical properties line into its unescaped key and value
+fn property(line: &str) -> Result<(String, String)> {
+    let mut chars = line.chars().peekable();
+    let mut key = String::new();
+    let mut separator = None;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => key.push(unescape(&mut chars)?),
+            '=' | ':' => {
+                separator = Some(c);
+                break;
+            },
+            c if c.is_whitespace() => {
+                separator = Some(' ');
+                break;
+            },
+            c => key.push(c),
+        }
+    }
+
+    // Whitespace may surround an `=` or `:` separator
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    if separator == Some(' ') && chars.next_if(|c| *c == '=' || *c == ':').is_some() {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        value.push(if c == '\\' { unescape(&mut chars)? } else { c });
+    }
+    Ok((key, value))
+}
+
+/// Decode the character following a backslash
+fn unescape(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<char> {
+    match chars.next() {
+        Some('t') => Ok('\t'),
+        Some('n') => Ok('\n'),
+        Some('r') => Ok('\r'),
+        Some('f') => Ok('\u{c}'),
+        Some('u') => {
+            let unit = code_unit(chars)?;
+            // Characters beyond U+FFFF are written as a surrogate pair of escapes
+            if (0xD800..0xDC00).contains(&unit) {
+                let mut ahead = chars.clone();
+                if ahead.next() == Some('\\') && ahead.next() == Some('u')
+                    && let Ok(low @ 0xDC00..0xE000) = code_unit(&mut ahead)
+                {
+                    *chars = ahead;
+                    let c = 0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00);
+                    return char::from_u32(c).context("Malformed surrogate pair");
+                }
+            }
+            char::from_u32(unit).context(format!("Unpaired surrogate \\u{:04x}", unit))
+        },
+        Some(c) => Ok(c),
+        None => Ok('\\'),
+    }
+}
+
+/// Read the four hex digits of a `\u` escape as a UTF-16 code unit
+fn code_unit(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<u32> {
+    let hex: String = chars.by_ref().take(4).collect();
+    u32::from_str_radix(&hex, 16).ok()
+        .filter(|_| hex.len() == 4)
+        .context(format!("Malformed \\u{} escape", hex))
+}
+
+/// Map a dotenv file to an object of variables
+///
+/// Lines are `KEY=value`, optionally prefixed with `export`. Single-quoted
+/// values are taken literally and double-quoted ones decode `\n`, `\t`, `\"`
+/// and `\\`; both may span several lines. Unquoted values end at a ` #`
+/// comment. References such as `${HOME}` are not expanded, so values read
+/// exactly as the file states them.
+pub fn parse_env(text: &str, expand_keys: bool) -> Result<Value> {
+    let mut obj = Map::new();
+    let mut lines = text.lines().enumerate();
+    while let Some((idx, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, rest) = line.split_once('=')
+            .context(format!("Failed to parse .env input at line {}: expected KEY=value", idx + 1))?;
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(anyhow!("Failed to parse .env input at line {}: invalid variable name '{}'", idx + 1, key));
+        }
+
+        let rest = rest.trim_start();
+        let value = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let mut quoted = rest[1..].to_string();
+                loop {
+                    if let Some(value) = closed_value(&quoted, quote) {
+                        break value;
+                    }
+                    let (_, next) = lines.next()
+                        .context(format!("Failed to parse .env input at line {}: unterminated {} quote", idx + 1, quote))?;
+                    quoted.push('\n');
+                    quoted.push_str(next);
+                }
+            },
+            _ => match rest.find(" #").or_else(|| rest.find("\t#")) {
+                Some(comment) => rest[..comment].trim_end().to_string(),
+                None => rest.trim_end().to_string(),
+            },
+        };
+        insert(&mut obj, &key_path(key, expand_keys), Value::String(value))?;
+    }
+    Ok(Value::Object(obj))
+}
+
+/// The value of a quoted dotenv string, if its closing quote has been reached
+fn closed_value(quoted: &str, quote: char) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c == quote => return Some(value),
+            '\\' if quote == '"' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('r') => value.push('\r'),
+                Some(c @ ('"' | '\\' | '$')) => value.push(c),
+                Some(c) => {
+                    value.push('\\');
+                    value.push(c);
+                },
+                None => value.push('\\'),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// The members a key is stored under: one per dot when expanding, otherwise the key itself
+fn key_path(key: &str, expand_keys: bool) -> Vec<String> {
+    if expand_keys {
+        key.split('.').map(str::to_string).collect()
+    } else {
+        vec![key.to_string()]
+    }
+}
+
+/// Store a value under nested members, creating objects along the way
+fn insert(obj: &mut Map<String, Value>, path: &[String], value: Value) -> Result<()> {
+    let Some((last, parents)) = path.split_last() else {
+        return Ok(());
+    };
+    let mut current = obj;
+    for (depth, segment) in parents.iter().enumerate() {
+        current = match current.entry(segment.clone()).or_insert_with(|| Value::Object(Map::new())) {
+            Value::Object(child) => child,
+            _ => return Err(anyhow!(
+                "Cannot store key '{}': '{}' already holds a value",
+                path.join("."), parents[..=depth].join(".")
+            )),
+        };
+    }
+    if current.get(last).is_some_and(Value::is_object) {
+        return Err(anyhow!("Cannot store key '{}': it already holds nested keys", path.join(".")));
+    }
+    current.insert(last.clone(), value);
+    Ok(())
+}
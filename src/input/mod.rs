@@ -0,0 +1,462 @@
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+mod binary;
+mod keyvalue;
+mod xml;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// JSON format
+    Json,
+    /// YAML format
+    Yaml,
+    /// TOML format
+    Toml,
+    /// JSON5 (comments, trailing commas, unquoted keys, single quotes, hex numbers...)
+    Json5,
+    /// JSON with comments and trailing commas, as used by VS Code and tsconfig.json
+    Jsonc,
+    /// Comma-separated values, read as an array of records
+    Csv,
+    /// Tab-separated values, read as an array of records
+    Tsv,
+    /// XML, with attributes as `@name` members and text as `#text`
+    Xml,
+    /// INI files, with a nested object per section
+    Ini,
+    /// Java `.properties` files
+    Properties,
+    /// dotenv files of `KEY=value` lines
+    Env,
+    /// HCL, as used by Terraform, mapped like Terraform's JSON syntax
+    Hcl,
+    /// MessagePack binary documents
+    Msgpack,
+    /// CBOR binary documents
+    Cbor,
+}
+
+/// Settings for formats that need more than their text to be parsed
+#[derive(Debug, Clone)]
+pub struct InputOptions {
+    /// Field delimiter for CSV/TSV (defaults to ',' for CSV and a tab for TSV)
+    pub delimiter: Option<u8>,
+    /// Quote character for CSV/TSV
+    pub quote: u8,
+    /// Whether the first CSV/TSV row holds the column names
+    pub header: bool,
+    /// Turn CSV/TSV numbers and booleans into typed values, and empty cells into null
+    pub infer_types: bool,
+    /// XML elements that always map to an array, even when they appear once
+    pub xml_arrays: Vec<String>,
+    /// Split INI, properties and dotenv keys on dots into nested objects
+    pub expand_keys: bool,
+}
+
+impl Default for InputOptions {
+    fn default() -> Self {
+        InputOptions {
+            delimiter: None,
+            quote: b'"',
+            header: true,
+            infer_types: false,
+            xml_arrays: Vec::new(),
+            expand_keys: false,
+        }
+    }
+}
+
+/// Determine the input format from the explicit format flag, the file
+/// extension, or failing those the content itself
+///
+/// Content that is not UTF-8 text is taken to be MessagePack or CBOR.
+pub fn determine_input_format(
+    file_path: &Option<PathBuf>,
+    explicit_format: Option<InputFormat>,
+    content: &[u8],
+) -> Result<InputFormat> {
+    // If format is explicitly specified, use that
+    if let Some(format) = explicit_format {
+        return Ok(format);
+    }
+
+    // If we have a file, try to determine format from extension
+    if let Some(path) = file_path
+        && let Some(format) = format_from_extension(path)
+    {
+        return Ok(format);
+    }
+
+    // Stdin and unknown extensions are recognised by their content
+    match std::str::from_utf8(content) {
+        Ok(text) => sniff_format(text),
+        Err(_) => binary::sniff_binary(content),
+    }
+}
+
+/// The input format matching a file's extension, if it is one decode reads
+///
+/// dotenv files are also recognised by name: `.env` and `.env.local` have no
+/// extension of their own.
+pub fn format_from_extension(path: &Path) -> Option<InputFormat> {
+    let name = path.file_name()?.to_str()?;
+    if name == ".env" || name.starts_with(".env.") {
+        return Some(InputFormat::Env);
+    }
+    let ext = path.extension()?.to_str()?;
+    match ext.to_lowercase().as_str() {
+        "json" => Some(InputFormat::Json),
+        "yml" | "yaml" => Some(InputFormat::Yaml),
+        "toml" => Some(InputFormat::Toml),
+        "json5" => Some(InputFormat::Json5),
+        "jsonc" => Some(InputFormat::Jsonc),
+        "csv" => Some(InputFormat::Csv),
+        "tsv" | "tab" => Some(InputFormat::Tsv),
+        "xml" => Some(InputFormat::Xml),
+        "ini" | "cfg" => Some(InputFormat::Ini),
+        "properties" => Some(InputFormat::Properties),
+        "env" => Some(InputFormat::Env),
+        "hcl" | "tf" | "tfvars" => Some(InputFormat::Hcl),
+        "msgpack" | "mpk" => Some(InputFormat::Msgpack),
+        "cbor" => Some(InputFormat::Cbor),
+        _ => None,
+    }
+}
+
+/// Guess the format of a document from its content
+///
+/// A document starting with `{`, or with `[` other than a `[table]` header
+/// followed by more lines, is JSON, or JSONC when it has comments or trailing
+/// commas, and a document starting with `<` is XML. Otherwise every significant
+/// line is classified: document markers (`---`, `%YAML`), `key: value` lines
+/// and `- item` lines point to YAML, while `[table]` headers and `key = value`
+/// lines point to TOML, or to HCL when only HCL parses them. When both kinds
+/// appear, the format that actually parses wins.
+pub fn sniff_format(content: &str) -> Result<InputFormat> {
+    let content = content.trim_start_matches('\u{feff}');
+    if content.trim().is_empty() {
+        return Err(anyhow!("Cannot detect the format of empty input; use -i/--input-format"));
+    }
+
+    // The first character settles JSON and XML, so their parsers report any errors
+    let start = content.trim_start();
+    let first_line = start.lines().next().unwrap_or_default();
+    let toml_header = is_toml_header(first_line) && !start[first_line.len()..].trim().is_empty();
+    if start.starts_with(['{', '/']) || (start.starts_with('[') && !toml_header) {
+        return Ok(if strip_jsonc(content) == content { InputFormat::Json } else { InputFormat::Jsonc });
+    }
+    if start.starts_with('<') {
+        return Ok(InputFormat::Xml);
+    }
+
+    let mut yaml_lines = 0;
+    let mut toml_lines = 0;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if is_yaml_marker(line) || is_yaml_entry(line) {
+            yaml_lines += 1;
+        } else if is_toml_header(line) || is_toml_assignment(line) {
+            toml_lines += 1;
+        }
+    }
+
+    match (yaml_lines > 0, toml_lines > 0) {
+        (true, false) => Ok(InputFormat::Yaml),
+        // Terraform files share `key = value` lines with TOML but add `block "label" {` syntax
+        (false, true) if toml::from_str::<toml::Table>(content).is_err()
+            && hcl::from_str::<serde::de::IgnoredAny>(content).is_ok() => Ok(InputFormat::Hcl),
+        (false, true) => Ok(InputFormat::Toml),
+        (true, true) if toml::from_str::<toml::Table>(content).is_ok() => Ok(InputFormat::Toml),
+        (true, true) if serde_yaml::from_str::<serde_yaml::Value>(content).is_ok() => Ok(InputFormat::Yaml),
+        (true, true) => Err(anyhow!(
+            "Cannot detect the input format: it mixes YAML and TOML syntax ({} YAML-like and {} TOML-like lines); use -i/--input-format",
+            yaml_lines, toml_lines
+        )),
+        (false, false) => Err(anyhow!(
+            "Cannot detect the input format: it doesn't look like JSON, XML, YAML or TOML; use -i/--input-format"
+        )),
+    }
+}
+
+/// `---`, `...` and `%YAML` directives only appear in YAML
+fn is_yaml_marker(line: &str) -> bool {
+    line == "---" || line.starts_with("--- ") || line == "..." || line.starts_with("%YAML")
+}
+
+/// A `key: value` mapping entry or a `- item` sequence entry
+fn is_yaml_entry(line: &str) -> bool {
+    if line == "-" || line.starts_with("- ") {
+        return true;
+    }
+    let key = match line.find(": ") {
+        Some(idx) => &line[..idx],
+        None => match line.strip_suffix(':') {
+            Some(key) => key,
+            None => return false,
+        },
+    };
+    !key.is_empty() && !key.contains('=') && !key.starts_with('[')
+}
+
+/// A `[table]` or `[[array.of.tables]]` header
+fn is_toml_header(line: &str) -> bool {
+    // Drop a trailing comment
+    let line = line.split(" #").next().unwrap_or(line).trim_end();
+    let name = line.strip_prefix("[[").and_then(|rest| rest.strip_suffix("]]"))
+        .or_else(|| line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')));
+    name.is_some_and(|name| {
+        !name.trim().is_empty()
+            && name.chars().all(|c| c.is_alphanumeric() || "_-.\"' ".contains(c))
+    })
+}
+
+/// A `key = value` assignment with a bare, quoted or dotted key
+fn is_toml_assignment(line: &str) -> bool {
+    let Some((key, value)) = line.split_once('=') else {
+        return false;
+    };
+    let key = key.trim();
+    !key.is_empty()
+        && !value.trim().is_empty()
+        && key.chars().all(|c| c.is_alphanumeric() || "_-.\"' ".contains(c))
+}
+
+/// Parse input bytes according to the specified format
+///
+/// Text formats must be valid UTF-8; binary formats are decoded as they are.
+pub fn parse_bytes(content: &[u8], format: InputFormat, options: &InputOptions) -> Result<Value> {
+    match format {
+        InputFormat::Msgpack => binary::parse_msgpack(content),
+        InputFormat::Cbor => binary::parse_cbor(content),
+        _ => {
+            let text = std::str::from_utf8(content)
+                .context("Input is not valid UTF-8 text; use -i msgpack or -i cbor for binary input")?;
+            parse_input(text, format, options)
+        },
+    }
+}
+
+/// Parse input data according to the specified format
+pub fn parse_input(input_text: &str, format: InputFormat, options: &InputOptions) -> Result<Value> {
+    match format {
+        InputFormat::Json => {
+            serde_json::from_str(input_text).with_context(|| {
+                if serde_json::from_str::<serde::de::IgnoredAny>(&strip_jsonc(input_text)).is_ok() {
+                    "Failed to parse JSON input (it has comments or trailing commas; use --lenient to accept them)"
+                } else {
+                    "Failed to parse JSON input"
+                }
+            })
+        },
+        InputFormat::Yaml => {
+            serde_yaml::from_str(input_text)
+                .context("Failed to parse YAML input")
+        },
+        InputFormat::Json5 => {
+            json5::from_str(input_text)
+                .context("Failed to parse JSON5 input")
+        },
+        InputFormat::Jsonc => {
+            serde_json::from_str(&strip_jsonc(input_text))
+                .context("Failed to parse JSONC input")
+        },
+        InputFormat::Csv | InputFormat::Tsv => {
+            let default_delimiter = if format == InputFormat::Csv { b',' } else { b'\t' };
+            parse_delimited(input_text, options.delimiter.unwrap_or(default_delimiter), options)
+                .context(format!("Failed to parse {} input", if format == InputFormat::Csv { "CSV" } else { "TSV" }))
+        },
+        InputFormat::Xml => xml::parse_xml(input_text, &options.xml_arrays),
+        InputFormat::Ini => keyvalue::parse_ini(input_text, options.expand_keys),
+        InputFormat::Properties => keyvalue::parse_properties(input_text, options.expand_keys),
+        InputFormat::Env => keyvalue::parse_env(input_text, options.expand_keys),
+        InputFormat::Hcl => hcl::from_str(input_text)
+            .context("Failed to parse HCL input"),
+        InputFormat::Msgpack => binary::parse_msgpack(input_text.as_bytes()),
+        InputFormat::Cbor => binary::parse_cbor(input_text.as_bytes()),
+        InputFormat::Toml => {
+            let value = toml::from_str(input_text)
+                .context("Failed to parse TOML input")?;
+            
+            // Convert toml::Value to serde_json::Value for consistent querying
+            toml_to_json_value(value)
+        },
+    }
+}
+
+/// Read delimited text as an array of objects keyed by the header row, or as
+/// an array of arrays when there is no header
+fn parse_delimited(text: &str, delimiter: u8, options: &InputOptions) -> Result<Value> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quote(options.quote)
+        .has_headers(options.header)
+        .from_reader(text.as_bytes());
+
+    let headers: Vec<String> = if options.header {
+        unique_headers(reader.headers()?)
+    } else {
+        Vec::new()
+    };
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let cells = record.iter().map(|cell| csv_cell(cell, options.infer_types));
+        rows.push(if options.header {
+            Value::Object(headers.iter().cloned().zip(cells).collect())
+        } else {
+            Value::Array(cells.collect())
+        });
+    }
+    Ok(Value::Array(rows))
+}
+
+/// Make repeated header names unique so no column is lost
+///
+/// The second `a` becomes `a_2`, the third `a_3` and so on, skipping names that
+/// another column already uses.
+fn unique_headers(record: &csv::StringRecord) -> Vec<String> {
+    let mut headers: Vec<String> = Vec::new();
+    for name in record {
+        let mut unique = name.to_string();
+        let mut count = 1;
+        while headers.contains(&unique) || (count > 1 && record.iter().any(|other| other == unique)) {
+            count += 1;
+            unique = format!("{}_{}", name, count);
+        }
+        headers.push(unique);
+    }
+    headers
+}
+
+/// Convert a CSV cell to a value, inferring its type when asked to
+fn csv_cell(cell: &str, infer_types: bool) -> Value {
+    if !infer_types {
+        return Value::String(cell.to_string());
+    }
+    match cell {
+        "" => Value::Null,
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => {
+            // Leading zeros mark identifiers such as zip codes rather than numbers
+            let digits = cell.trim_start_matches(['-', '+']).as_bytes();
+            if digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit() {
+                Value::String(cell.to_string())
+            } else if let Ok(i) = cell.parse::<i64>() {
+                Value::from(i)
+            } else if let Some(n) = cell.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+                Value::Number(n)
+            } else {
+                Value::String(cell.to_string())
+            }
+        },
+    }
+}
+
+/// Blank out the comments and trailing commas of JSONC text, leaving plain JSON
+///
+/// Removed characters are replaced with spaces (line breaks are kept), so
+/// parse errors still point at the right line and column of the original.
+pub fn strip_jsonc(text: &str) -> String {
+    let mut bytes = text.as_bytes().to_vec();
+
+    // Comments first, so that they can't hide a trailing comma
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'"', _) => i = skip_string(&bytes, i),
+            (b'/', Some(b'/')) => {
+                let end = text[i..].find('\n').map_or(bytes.len(), |offset| i + offset);
+                blank(&mut bytes[i..end]);
+                i = end;
+            },
+            (b'/', Some(b'*')) => {
+                let end = text[i + 2..].find("*/").map_or(bytes.len(), |offset| i + 2 + offset + 2);
+                blank(&mut bytes[i..end]);
+                i = end;
+            },
+            _ => i += 1,
+        }
+    }
+
+    // Then commas followed only by whitespace and a closing bracket
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => i = skip_string(&bytes, i),
+            b',' => {
+                let next = bytes[i + 1..].iter().find(|byte| !byte.is_ascii_whitespace());
+                if matches!(next, Some(b'}' | b']')) {
+                    bytes[i] = b' ';
+                }
+                i += 1;
+            },
+            _ => i += 1,
+        }
+    }
+
+    // Only ASCII bytes were replaced, and only with spaces, so the text is still UTF-8
+    String::from_utf8(bytes).expect("blanking ASCII bytes keeps UTF-8 valid")
+}
+
+/// Replace every byte but line breaks with a space
+fn blank(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut().filter(|byte| **byte != b'\n') {
+        *byte = b' ';
+    }
+}
+
+/// Index just past the JSON string starting at `start`
+fn skip_string(bytes: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    i
+}
+
+/// Convert a TOML value to a JSON value
+fn toml_to_json_value(toml_value: toml::Value) -> Result<Value> {
+    match toml_value {
+        toml::Value::String(s) => Ok(Value::String(s)),
+        toml::Value::Integer(i) => Ok(Value::Number(serde_json::Number::from(i))),
+        toml::Value::Float(f) => {
+            // Convert float to Number (must be valid, non-NaN, non-infinite)
+            if f.is_finite() {
+                match serde_json::Number::from_f64(f) {
+                    Some(n) => Ok(Value::Number(n)),
+                    None => Err(anyhow!("Cannot represent float in JSON: {}", f)),
+                }
+            } else {
+                Err(anyhow!("JSON cannot represent non-finite float: {}", f))
+            }
+        },
+        toml::Value::Boolean(b) => Ok(Value::Bool(b)),
+        toml::Value::Datetime(dt) => Ok(Value::String(dt.to_string())),
+        toml::Value::Array(arr) => {
+            let mut json_array = Vec::new();
+            for item in arr {
+                json_array.push(toml_to_json_value(item)?);
+            }
+            Ok(Value::Array(json_array))
+        },
+        toml::Value::Table(table) => {
+            let mut map = serde_json::Map::new();
+            for (key, value) in table {
+                map.insert(key, toml_to_json_value(value)?);
+            }
+            Ok(Value::Object(map))
+        }
+    }
+}
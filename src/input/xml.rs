@@ -0,0 +1,82 @@
+use anyhow::{Context, Result};
+use roxmltree::{Document, Node, ParsingOptions};
+use serde_json::{Map, Value};
+
+/// Map an XML document to a JSON value
+///
+/// The root element becomes the only member of an object. An element with
+/// neither attributes nor child elements maps to its text, or to null when it
+/// is empty. Any other element maps to an object holding its attributes as
+/// `@name` members, its child elements by name and its text as `#text`.
+/// Child elements sharing a name become an array in document order, as do
+/// elements named in `arrays` even when they appear once. Text is never
+/// converted to numbers or booleans; comments and processing instructions
+/// are dropped.
+pub fn parse_xml(text: &str, arrays: &[String]) -> Result<Value> {
+    let options = ParsingOptions { allow_dtd: true, ..ParsingOptions::default() };
+    let document = Document::parse_with_options(text, options)
+        .context("Failed to parse XML input")?;
+    let root = document.root_element();
+
+    let mut obj = Map::new();
+    obj.insert(qualified_name(root), element_value(root, arrays));
+    Ok(Value::Object(obj))
+}
+
+fn element_value(element: Node, arrays: &[String]) -> Value {
+    let text = element_text(element);
+    let has_children = element.children().any(|child| child.is_element());
+    if element.attributes().len() == 0 && !has_children {
+        return text.map_or(Value::Null, Value::String);
+    }
+
+    let mut obj = Map::new();
+    for attribute in element.attributes() {
+        let name = match attribute.namespace().and_then(|uri| element.lookup_prefix(uri)) {
+            Some(prefix) => format!("@{}:{}", prefix, attribute.name()),
+            None => format!("@{}", attribute.name()),
+        };
+        obj.insert(name, Value::String(attribute.value().to_string()));
+    }
+    for child in element.children().filter(Node::is_element) {
+        let name = qualified_name(child);
+        let value = element_value(child, arrays);
+        match obj.get_mut(&name) {
+            // Element values are never arrays themselves, so an array is a group of siblings
+            Some(Value::Array(items)) => items.push(value),
+            Some(existing) => {
+                let first = existing.take();
+                *existing = Value::Array(vec![first, value]);
+            },
+            None if arrays.contains(&name) => {
+                obj.insert(name, Value::Array(vec![value]));
+            },
+            None => {
+                obj.insert(name, value);
+            },
+        }
+    }
+    if let Some(text) = text {
+        obj.insert("#text".to_string(), Value::String(text));
+    }
+    Value::Object(obj)
+}
+
+/// The text and CDATA directly inside an element, trimmed, if there is any
+fn element_text(element: Node) -> Option<String> {
+    let text: String = element.children()
+        .filter(Node::is_text)
+        .filter_map(|child| child.text())
+        .collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// An element name with its namespace prefix as written, such as `soap:Body`
+fn qualified_name(element: Node) -> String {
+    let tag = element.tag_name();
+    match tag.namespace().and_then(|uri| element.lookup_prefix(uri)) {
+        Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, tag.name()),
+        _ => tag.name().to_string(),
+    }
+}
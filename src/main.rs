@@ -1,43 +1,214 @@
 mod parser;
 mod engine;
+mod edit;
+mod diff;
+mod merge;
+mod input;
+mod output;
 mod tests;
 
 use anyhow::{Context, Result, anyhow};
-use clap::{Parser, ValueEnum};
-use std::fs::File;
-use std::io::{self, Read};
-use std::path::PathBuf;
-use serde_json::Value;
+use clap::{Parser, Subcommand, ValueEnum};
+use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
+use rayon::prelude::*;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use serde_json::{Value, json};
+use input::InputFormat;
 
 /// Decode - A high-performance query tool for JSON, YAML, and TOML data
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    /// The query string
-    #[arg(index = 1)]
-    query: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Input file (defaults to stdin if not provided)
-    #[arg(short, long)]
-    file: Option<PathBuf>,
+    /// The query string (JSONPath, or a JSON Pointer when it starts with '/')
+    #[arg(index = 1, required = true)]
+    query: Option<String>,
+
+    /// Treat the query as an RFC 6901 JSON Pointer
+    #[arg(long, global = true)]
+    pointer: bool,
+
+    /// Input file or glob pattern, repeatable (defaults to stdin if not provided)
+    #[arg(short, long, global = true)]
+    file: Vec<PathBuf>,
+
+    /// Search every file below DIR with a supported extension, honouring .gitignore (repeatable)
+    #[arg(short = 'R', long, value_name = "DIR")]
+    recursive: Vec<PathBuf>,
+
+    /// Only search files matching GLOB with --recursive (repeatable)
+    #[arg(long, value_name = "GLOB", requires = "recursive")]
+    include: Vec<String>,
+
+    /// Skip files and directories matching GLOB with --recursive (repeatable)
+    #[arg(long, value_name = "GLOB", requires = "recursive")]
+    exclude: Vec<String>,
+
+    /// Don't prefix results with the file name when querying several files
+    #[arg(long)]
+    no_filename: bool,
+
+    /// Print each file's result as a {"file", "result"} object
+    #[arg(long, conflicts_with_all = ["stream_results", "nul", "paths", "with_paths", "merge"])]
+    file_objects: bool,
+
+    /// Deep-merge every input file, left to right, and query the merged document
+    #[arg(long)]
+    merge: bool,
+
+    /// How --merge combines arrays found at the same location
+    #[arg(long, value_enum, default_value_t = ArrayStrategy::Replace, requires = "merge")]
+    merge_arrays: ArrayStrategy,
+
+    /// Key identifying array elements with `--merge-arrays merge-by-key`
+    #[arg(long, value_name = "KEY", default_value = "name", requires = "merge")]
+    merge_key: String,
 
     /// Input format (autodetected from file extension if not specified)
-    #[arg(short = 'i', long, value_enum)]
+    #[arg(short = 'i', long, value_enum, global = true)]
     input_format: Option<InputFormat>,
 
+    /// Write the updated document back to the input file instead of printing it
+    #[arg(short = 'w', long, global = true)]
+    in_place: bool,
+
+    /// Print the edit as an RFC 6902 JSON Patch instead of the updated document
+    #[arg(long, global = true, conflicts_with = "in_place")]
+    emit_patch: bool,
+
+    /// Keep a copy of the original file with SUFFIX appended when editing in place
+    #[arg(long, value_name = "SUFFIX", num_args = 0..=1, require_equals = true, default_missing_value = ".bak", global = true, requires = "in_place")]
+    backup: Option<String>,
+
+    /// Accept comments and trailing commas in JSON input
+    #[arg(long, global = true)]
+    lenient: bool,
+
+    /// Read CSV/TSV input without a header row, as arrays instead of objects
+    #[arg(long, global = true)]
+    no_header: bool,
+
+    /// Turn numbers and booleans in CSV/TSV input into typed values, and empty cells into null
+    #[arg(long, global = true)]
+    infer_types: bool,
+
+    /// Field delimiter of CSV/TSV input, a single character or `tab`
+    #[arg(long, value_name = "CHAR", value_parser = parse_byte, global = true)]
+    delimiter: Option<u8>,
+
+    /// Quote character of CSV/TSV input
+    #[arg(long, value_name = "CHAR", value_parser = parse_byte, default_value = "\"", global = true)]
+    quote: u8,
+
+    /// XML element that always maps to an array, even when it appears once (repeatable)
+    #[arg(long, value_name = "NAME", global = true)]
+    xml_array: Vec<String>,
+
+    /// Expand dotted keys of INI, properties and .env input into nested objects
+    #[arg(long, global = true)]
+    expand_keys: bool,
+
     /// Output format
     #[arg(short, long, value_enum, default_value_t = OutputFormat::Compact)]
     output: OutputFormat,
+
+    /// Shorthand for `-o raw`: print strings verbatim, without quotes or escaping
+    #[arg(short = 'r', long, conflicts_with = "output")]
+    raw_output: bool,
+
+    /// Join the elements of an array result with SEP in raw output (defaults to newlines)
+    #[arg(long, value_name = "SEP")]
+    join: Option<String>,
+
+    /// Columns to write for CSV/TSV/table output (defaults to the union of all keys)
+    #[arg(long, value_delimiter = ',')]
+    columns: Option<Vec<String>>,
+
+    /// Flatten nested values into separate CSV/TSV columns joined by SEP (JSON-encoded otherwise)
+    #[arg(long, value_name = "SEP", num_args = 0..=1, default_missing_value = ".")]
+    flatten: Option<String>,
+
+    /// Draw table output with plain ASCII instead of Unicode box drawing characters
+    #[arg(long)]
+    ascii: bool,
+
+    /// Sort object keys alphabetically instead of keeping the document order
+    #[arg(long)]
+    sort_keys: bool,
+
+    /// Print every match on its own line as compact JSON (or raw with `-o raw`)
+    #[arg(long)]
+    stream_results: bool,
+
+    /// Like --stream-results, but terminate each match with a NUL byte instead of a newline
+    #[arg(short = '0', long)]
+    nul: bool,
+
+    /// Print the normalized path of every match instead of its value
+    #[arg(long, conflicts_with = "with_paths")]
+    paths: bool,
+
+    /// Print `path<TAB>value` for every match
+    #[arg(long)]
+    with_paths: bool,
+
+    /// Syntax used for paths printed by --paths and --with-paths
+    #[arg(long, value_enum, default_value_t = PathFormat::Normalized)]
+    path_format: PathFormat,
+
+    /// When to colourise JSON output (NO_COLOR disables `auto`; DECODE_COLORS sets the palette)
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
-enum InputFormat {
-    /// JSON format
-    Json,
-    /// YAML format
-    Yaml,
-    /// TOML format
-    Toml,
+#[derive(Subcommand)]
+enum Command {
+    /// Assign a JSON value to every node matched by a query and print the updated document
+    Set {
+        /// The query selecting the nodes to update
+        query: String,
+
+        /// The new value as JSON, e.g. '"0.2.0"', '42' or '{"enabled": true}'
+        value: String,
+
+        /// Create missing intermediate objects along the path
+        #[arg(short = 'p', long)]
+        create: bool,
+    },
+
+    /// Remove every node matched by a query and print the updated document
+    Delete {
+        /// The query selecting the nodes to remove
+        query: String,
+    },
+
+    /// Apply an RFC 6902 JSON Patch file and print the updated document
+    Patch {
+        /// The JSON Patch file, an array of add/remove/replace/move/copy/test operations
+        patch: PathBuf,
+    },
+
+    /// Compare two documents, in any input formats, and print what was added, removed or changed
+    Diff {
+        /// The original document
+        left: PathBuf,
+
+        /// The document to compare it with
+        right: PathBuf,
+
+        /// A query narrowing both documents before comparing them
+        query: Option<String>,
+
+        /// Print the differences as a JSON array instead of text
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
@@ -46,151 +217,713 @@ enum OutputFormat {
     Pretty,
     /// Compact JSON (no extra whitespace)
     Compact,
-    /// Raw output (for strings)
+    /// Raw output (strings unquoted, arrays one element per line)
     Raw,
+    /// TOML document (object results only)
+    Toml,
+    /// Comma-separated values (arrays of objects)
+    Csv,
+    /// Tab-separated values (arrays of objects)
+    Tsv,
+    /// Aligned table for reading in a terminal
+    Table,
+    /// MessagePack binary document
+    Msgpack,
+    /// CBOR binary document
+    Cbor,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum ArrayStrategy {
+    /// Later arrays replace earlier ones
+    Replace,
+    /// Later arrays are appended to earlier ones
+    Append,
+    /// Objects sharing the --merge-key value are merged, other elements appended
+    MergeByKey,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum PathFormat {
+    /// RFC 9535 normalized path, e.g. $['store']['book'][0]
+    Normalized,
+    /// RFC 6901 JSON Pointer, e.g. /store/book/0
+    Pointer,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    /// Colourise when writing to a terminal and NO_COLOR is not set
+    Auto,
+    /// Always colourise
+    Always,
+    /// Never colourise
+    Never,
 }
 
 fn main() -> Result<()> {
     // Parse command-line arguments
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    if cli.raw_output {
+        cli.output = OutputFormat::Raw;
+    }
 
-    // Determine input format from file extension or explicit format flag
-    let input_format = determine_input_format(&cli.file, cli.input_format)?;
+    // diff exits with 1 when the documents differ and a recursive search when
+    // nothing matched, so their errors use 2 like diff(1) and grep
+    let reserves_status_1 = matches!(cli.command, Some(Command::Diff { .. })) || !cli.recursive.is_empty();
+    let error_status = if reserves_status_1 { 2 } else { 1 };
+    match run(&cli) {
+        Err(err) if error_status != 1 => {
+            eprintln!("Error: {:?}", err);
+            std::process::exit(error_status);
+        },
+        result => result,
+    }
+}
+
+fn run(cli: &Cli) -> Result<()> {
+    if cli.join.is_some() && cli.output != OutputFormat::Raw {
+        return Err(anyhow!("--join requires raw output (-o raw or -r)"));
+    }
+
+    // Comparing documents reads its own two files instead of --file or stdin
+    if let Some(Command::Diff { left, right, query, json }) = &cli.command {
+        if cli.in_place || cli.emit_patch {
+            return Err(anyhow!("diff does not edit documents, so --in-place and --emit-patch do not apply"));
+        }
+        return run_diff(left, right, query.as_deref(), *json, cli);
+    }
 
-    // Read the input data
-    let input = read_input(&cli.file, input_format)?;
+    if cli.in_place && cli.command.is_none() {
+        return Err(anyhow!("--in-place requires an editing command such as set or delete"));
+    }
+    if cli.in_place && cli.file.is_empty() {
+        return Err(anyhow!("--in-place requires an input file (-f); stdin cannot be written back"));
+    }
+    if cli.merge && cli.command.is_some() {
+        return Err(anyhow!("--merge cannot be combined with editing commands"));
+    }
+
+    // Glob patterns are expanded here so that they also work when quoted
+    let mut files = expand_files(&cli.file)?;
+    files.extend(walk_directories(&cli.recursive, &cli.include, &cli.exclude)?);
+    if (files.len() > 1 || !cli.recursive.is_empty()) && cli.command.is_some() {
+        return Err(anyhow!("Editing commands take a single input file"));
+    }
+
+    // Editing commands print the whole updated document instead of a result
+    if let Some(command) = &cli.command {
+        let file = files.first().cloned();
+        let (input_text, input_format, input) = load_input(&file, cli)?;
+        let updated = run_command(command, &input_text, &input, input_format, cli)?;
+        return match &file {
+            Some(path) if cli.in_place => write_in_place(path, &updated, cli.backup.as_deref()),
+            _ => {
+                print!("{}", updated);
+                Ok(())
+            },
+        };
+    }
 
     // Parse the query
-    let query = parser::parse_query(&cli.query)
-        .context(format!("Failed to parse query: {}", cli.query))?;
+    let query_text = cli.query.as_deref().context("Missing query")?;
+    let query = parse_query_arg(query_text, cli.pointer)?;
 
-    // Apply the query to the input data
-    let result = engine::apply_query(&input, &query)
-        .context("Failed to apply query")?;
+    // Pick a palette only when JSON output should be colourised
+    let palette = color_palette(cli.color)?;
+
+    if cli.merge {
+        // Layered configuration is queried as the single effective document
+        let input = merge_inputs(&files, cli)?;
+        return print_result(&input, &query, cli, palette.as_ref());
+    }
+
+    if files.len() > 1 || cli.file_objects || !cli.recursive.is_empty() {
+        if matches!(cli.output, OutputFormat::Msgpack | OutputFormat::Cbor) {
+            return Err(anyhow!(
+                "Binary output holds a single result, so it cannot be used with several files, --recursive or --file-objects; \
+                 combine files into one document with --merge"
+            ));
+        }
+        return query_files(&files, &query, cli, palette.as_ref());
+    }
+
+    // Read and parse the input data
+    let (_, _, input) = load_input(&files.first().cloned(), cli)?;
+
+    print_result(&input, &query, cli, palette.as_ref())?;
+    Ok(())
+}
+
+/// Apply the query to every input file and print each file's result
+///
+/// Files are read and queried in parallel, and their results printed in order.
+/// Results are prefixed with the file name (unless --no-filename is given), or
+/// wrapped in `{"file", "result"}` objects with --file-objects. A file that
+/// cannot be read or queried is reported and skipped, and the command fails
+/// once every other file has been processed.
+///
+/// A recursive search works like grep: files the query doesn't match are
+/// skipped silently, and the command exits with status 1 if none matched;
+/// `main` turns errors into status 2.
+fn query_files(files: &[PathBuf], query: &parser::Query, cli: &Cli, palette: Option<&output::Palette>) -> Result<()> {
+    let recursive = !cli.recursive.is_empty();
+
+    // Without files the only input is stdin
+    let files: Vec<Option<PathBuf>> = if files.is_empty() && !recursive {
+        vec![None]
+    } else {
+        files.iter().cloned().map(Some).collect()
+    };
+    let show_names = (files.len() > 1 || recursive) && !cli.no_filename && !cli.file_objects;
+    let terminator = if cli.nul { '\0' } else { '\n' };
+
+    let results: Vec<(String, Result<Option<String>>)> = files.par_iter()
+        .map(|file| {
+            let name = file.as_ref().map_or_else(|| "-".to_string(), |path| path.display().to_string());
+            let result = query_file(file, &name, query, cli, palette, recursive);
+            (name, result)
+        })
+        .collect();
+
+    let mut failed = 0;
+    let mut matched = 0;
+    let mut stdout = io::stdout().lock();
+    for (name, result) in &results {
+        let text = match result {
+            Ok(Some(text)) => text,
+            Ok(None) => continue,
+            Err(err) => {
+                eprintln!("{}: {:#}", name, err);
+                failed += 1;
+                continue;
+            },
+        };
+        matched += 1;
+
+        if show_names {
+            // Prefix every line, or every record with --nul, like grep does
+            for record in text.split_inclusive(terminator) {
+                write!(stdout, "{}:{}", name, record)
+                    .context("Failed to write result")?;
+            }
+        } else {
+            write!(stdout, "{}", text)
+                .context("Failed to write result")?;
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow!("Failed to query {} of {} files", failed, files.len()));
+    }
+    if recursive && matched == 0 {
+        stdout.flush().context("Failed to write result")?;
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Read a single input file and render the query result for it
+///
+/// With `skip_unmatched`, returns `None` when the query matches nothing.
+fn query_file(
+    file: &Option<PathBuf>,
+    name: &str,
+    query: &parser::Query,
+    cli: &Cli,
+    palette: Option<&output::Palette>,
+    skip_unmatched: bool,
+) -> Result<Option<String>> {
+    let (_, _, input) = load_input(file, cli)?;
+    if skip_unmatched && !engine::locate(&input, query).is_ok_and(|matches| !matches.is_empty()) {
+        return Ok(None);
+    }
+    render_result(&input, query, cli, palette, Some(name)).map(Some)
+}
+
+/// Every file decode can read below the given directories, in sorted order
+///
+/// Files ignored by `.gitignore`, `.ignore` and similar files are skipped, as
+/// are hidden files. `include` and `exclude` globs are matched like gitignore
+/// patterns against paths relative to each directory.
+fn walk_directories(dirs: &[PathBuf], include: &[String], exclude: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for dir in dirs {
+        let mut overrides = OverrideBuilder::new(dir);
+        for glob in include {
+            overrides.add(glob)
+                .context(format!("Invalid include pattern: {}", glob))?;
+        }
+        for glob in exclude {
+            overrides.add(&format!("!{}", glob))
+                .context(format!("Invalid exclude pattern: {}", glob))?;
+        }
+        let overrides = overrides.build()
+            .context("Invalid include or exclude patterns")?;
+
+        let walker = WalkBuilder::new(dir)
+            .overrides(overrides)
+            .require_git(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+        for entry in walker {
+            let entry = entry.context(format!("Failed to walk {}", dir.display()))?;
+            if entry.file_type().is_some_and(|kind| kind.is_file()) && input::format_from_extension(entry.path()).is_some() {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Apply the query to a document and render the result in the requested format
+///
+/// With --file-objects and a file name, the result is wrapped in a
+/// `{"file", "result"}` object before rendering.
+fn render_result(
+    input: &Value,
+    query: &parser::Query,
+    cli: &Cli,
+    palette: Option<&output::Palette>,
+    file: Option<&str>,
+) -> Result<String> {
+    // Streaming prints each matched node separately instead of one combined result
+    if cli.stream_results || cli.nul || cli.paths || cli.with_paths {
+        let matches = engine::locate(input, query)
+            .context("Failed to apply query")?;
+        return render_stream(matches, cli, palette);
+    }
 
-    // Output the result in the requested format
-    match cli.output {
+    let result = query_result(input, query, cli, file)?;
+
+    // Render the result in the requested format
+    let text = match cli.output {
+        OutputFormat::Pretty | OutputFormat::Compact if let Some(palette) = palette => {
+            let pretty = cli.output == OutputFormat::Pretty;
+            output::to_colored_json(&result, palette, pretty) + "\n"
+        },
         OutputFormat::Pretty => {
-            println!("{}", serde_json::to_string_pretty(&result)
-                .context("Failed to serialize result")?);
+            serde_json::to_string_pretty(&result)
+                .context("Failed to serialize result")? + "\n"
         },
         OutputFormat::Compact => {
-            println!("{}", serde_json::to_string(&result)
-                .context("Failed to serialize result")?);
+            serde_json::to_string(&result)
+                .context("Failed to serialize result")? + "\n"
         },
         OutputFormat::Raw => {
-            // For raw output, if the result is a simple value, output it without quotes
-            match result {
-                Value::String(s) => println!("{}", s),
-                Value::Number(n) => println!("{}", n),
-                Value::Bool(b) => println!("{}", b),
-                Value::Null => println!("null"),
-                _ => println!("{}", serde_json::to_string(&result)
-                    .context("Failed to serialize result")?),
-            }
+            // Array results print one element per line unless another separator is given
+            let separator = cli.join.as_deref().unwrap_or("\n");
+            output::to_raw_joined(&result, separator)
+                .map_or_else(String::new, |text| text + "\n")
+        },
+        OutputFormat::Toml => output::to_toml(&result)?,
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let delimiter = if cli.output == OutputFormat::Csv { b',' } else { b'\t' };
+            output::to_delimited(
+                &result,
+                delimiter,
+                cli.columns.as_deref(),
+                cli.flatten.as_deref(),
+            )?
         },
+        OutputFormat::Table => {
+            // Only fit the table to the terminal when writing to one
+            let max_width = terminal_size::terminal_size()
+                .map(|(terminal_size::Width(w), _)| w as usize);
+            output::to_table(
+                &result,
+                cli.columns.as_deref(),
+                cli.ascii,
+                max_width,
+            )
+        },
+        // Only print_result writes binary output, as one result for one document
+        OutputFormat::Msgpack | OutputFormat::Cbor => {
+            return Err(anyhow!("MessagePack and CBOR output can only be written for a single result"));
+        },
+    };
+
+    Ok(text)
+}
+
+/// Apply the query to a document, giving the value to render
+fn query_result(input: &Value, query: &parser::Query, cli: &Cli, file: Option<&str>) -> Result<Value> {
+    let mut result = engine::apply_query(input, query)
+        .context("Failed to apply query")?;
+    if cli.file_objects && let Some(file) = file {
+        result = json!({"file": file, "result": result});
     }
 
-    Ok(())
+    // Keys keep their document order unless sorting is requested
+    if cli.sort_keys {
+        output::sort_keys(&mut result);
+    }
+    Ok(result)
 }
 
-/// Determine the input format from file extension or explicit format flag
-fn determine_input_format(
-    file_path: &Option<PathBuf>,
-    explicit_format: Option<InputFormat>,
-) -> Result<InputFormat> {
-    // If format is explicitly specified, use that
-    if let Some(format) = explicit_format {
-        return Ok(format);
+/// Print the result of a query on a single document
+///
+/// MessagePack and CBOR are written as raw bytes, which are never sent to a
+/// terminal.
+fn print_result(input: &Value, query: &parser::Query, cli: &Cli, palette: Option<&output::Palette>) -> Result<()> {
+    let encode = match cli.output {
+        OutputFormat::Msgpack => output::to_msgpack,
+        OutputFormat::Cbor => output::to_cbor,
+        _ => {
+            print!("{}", render_result(input, query, cli, palette, None)?);
+            return Ok(());
+        },
+    };
+    if cli.stream_results || cli.nul || cli.paths || cli.with_paths {
+        return Err(anyhow!("Printing one result per line only supports compact and raw output"));
     }
 
-    // If we have a file, try to determine format from extension
-    if let Some(path) = file_path {
-        if let Some(ext) = path.extension() {
-            if let Some(ext_str) = ext.to_str() {
-                match ext_str.to_lowercase().as_str() {
-                    "json" => return Ok(InputFormat::Json),
-                    "yml" | "yaml" => return Ok(InputFormat::Yaml),
-                    "toml" => return Ok(InputFormat::Toml),
-                    _ => {}
-                }
-            }
-        }
+    let mut stdout = io::stdout().lock();
+    if stdout.is_terminal() {
+        return Err(anyhow!("Refusing to write binary output to a terminal; redirect it to a file or a pipe"));
     }
+    let bytes = encode(&query_result(input, query, cli, None)?)?;
+    stdout.write_all(&bytes)
+        .and_then(|_| stdout.flush())
+        .context("Failed to write result")
+}
+
+/// Expand glob patterns among the input files, keeping plain paths as they are
+///
+/// Matches of each pattern are sorted, and a pattern matching nothing is an error.
+fn expand_files(patterns: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        let text = pattern.to_string_lossy();
+        if pattern.exists() || !text.contains(['*', '?', '[']) {
+            files.push(pattern.clone());
+            continue;
+        }
 
-    // Default to JSON if format cannot be determined
-    Ok(InputFormat::Json)
+        let mut matches: Vec<PathBuf> = glob::glob(&text)
+            .context(format!("Invalid glob pattern: {}", text))?
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file())
+            .collect();
+        if matches.is_empty() {
+            return Err(anyhow!("No files match pattern: {}", text));
+        }
+        matches.sort();
+        files.append(&mut matches);
+    }
+    Ok(files)
 }
 
-/// Read input data from file or stdin and parse according to the specified format
-fn read_input(file_path: &Option<PathBuf>, format: InputFormat) -> Result<Value> {
-    let input_text = if let Some(path) = file_path {
-        // Read from file
-        let mut file = File::open(path)
-            .context(format!("Failed to open file: {}", path.display()))?;
-        let mut content = String::new();
-        file.read_to_string(&mut content)
-            .context("Failed to read file")?;
-        content
+/// Parse a query argument, as a JSON Pointer when asked to or when it looks like one
+fn parse_query_arg(query: &str, pointer: bool) -> Result<parser::Query> {
+    if pointer || query.starts_with('/') {
+        parser::parse_pointer(query)
+            .context(format!("Failed to parse JSON Pointer: {}", query))
     } else {
-        // Read from stdin
-        let mut buffer = String::new();
-        io::stdin().read_to_string(&mut buffer)
-            .context("Failed to read from stdin")?;
-        buffer
-    };
+        parser::parse_query(query)
+            .context(format!("Failed to parse query: {}", query))
+    }
+}
 
-    // Parse according to format
-    match format {
-        InputFormat::Json => {
-            serde_json::from_str(&input_text)
-                .context("Failed to parse JSON input")
+/// Run an editing command and return the updated document in its input format,
+/// or the equivalent JSON Patch with `--emit-patch`
+///
+/// YAML and TOML documents are edited in place in their source text, so that
+/// comments and formatting outside the changed values are kept.
+fn run_command(command: &Command, text: &str, document: &Value, format: InputFormat, cli: &Cli) -> Result<String> {
+    let operations = match command {
+        Command::Set { query, value, create } => {
+            let query = parse_query_arg(query, cli.pointer)?;
+            let new_value: Value = serde_json::from_str(value)
+                .context(format!("Value must be valid JSON (quote strings, e.g. '\"{}\"')", value))?;
+            let operations = edit::plan_set(document, &query, &new_value, *create)
+                .context(if *create {
+                    "Failed to set value"
+                } else {
+                    "Failed to set value (use --create to add missing fields)"
+                })?;
+            if operations.is_empty() {
+                return Err(anyhow!("Query did not match any value"));
+            }
+            operations
         },
-        InputFormat::Yaml => {
-            serde_yaml::from_str(&input_text)
-                .context("Failed to parse YAML input")
+        Command::Delete { query } => {
+            let query = parse_query_arg(query, cli.pointer)?;
+            edit::plan_delete(document, &query)
+                .context("Failed to delete value")?
         },
-        InputFormat::Toml => {
-            let value = toml::from_str(&input_text)
-                .context("Failed to parse TOML input")?;
-            
-            // Convert toml::Value to serde_json::Value for consistent querying
-            toml_to_json_value(value)
+        Command::Patch { patch } => {
+            let patch_text = fs::read_to_string(patch)
+                .context(format!("Failed to read patch file: {}", patch.display()))?;
+            let patch: Value = serde_json::from_str(&patch_text)
+                .context("Failed to parse JSON Patch")?;
+            edit::plan_patch(document, &patch)
+                .context("Failed to apply patch")?
         },
+        Command::Diff { .. } => unreachable!("diff is handled before reading the input"),
+    };
+
+    if cli.emit_patch {
+        let patch = serde_json::to_string_pretty(&edit::to_patch(&operations))
+            .context("Failed to serialize JSON Patch")?;
+        return Ok(patch + "\n");
     }
-}
 
-/// Convert a TOML value to a JSON value
-fn toml_to_json_value(toml_value: toml::Value) -> Result<Value> {
-    match toml_value {
-        toml::Value::String(s) => Ok(Value::String(s)),
-        toml::Value::Integer(i) => Ok(Value::Number(serde_json::Number::from(i))),
-        toml::Value::Float(f) => {
-            // Convert float to Number (must be valid, non-NaN, non-infinite)
-            if f.is_finite() {
-                match serde_json::Number::from_f64(f) {
-                    Some(n) => Ok(Value::Number(n)),
-                    None => Err(anyhow!("Cannot represent float in JSON: {}", f)),
-                }
-            } else {
-                Err(anyhow!("JSON cannot represent non-finite float: {}", f))
-            }
+    match format {
+        // JSON5 and JSONC are written back as plain JSON
+        InputFormat::Json | InputFormat::Json5 | InputFormat::Jsonc => {
+            let mut document = document.clone();
+            edit::apply(&mut document, &operations)?;
+            let text = serde_json::to_string_pretty(&document)
+                .context("Failed to serialize JSON document")?;
+            Ok(text + "\n")
         },
-        toml::Value::Boolean(b) => Ok(Value::Bool(b)),
-        toml::Value::Datetime(dt) => Ok(Value::String(dt.to_string())),
-        toml::Value::Array(arr) => {
-            let mut json_array = Vec::new();
-            for item in arr {
-                json_array.push(toml_to_json_value(item)?);
-            }
-            Ok(Value::Array(json_array))
+        InputFormat::Yaml => edit::apply_to_yaml(text, &operations)
+            .context("Failed to update YAML document"),
+        InputFormat::Toml => edit::apply_to_toml(text, &operations)
+            .context("Failed to update TOML document"),
+        InputFormat::Csv | InputFormat::Tsv | InputFormat::Xml
+            | InputFormat::Ini | InputFormat::Properties | InputFormat::Env | InputFormat::Hcl
+            | InputFormat::Msgpack | InputFormat::Cbor => {
+            Err(anyhow!("Editing commands only support JSON, YAML and TOML documents"))
         },
-        toml::Value::Table(table) => {
-            let mut map = serde_json::Map::new();
-            for (key, value) in table {
-                map.insert(key, toml_to_json_value(value)?);
-            }
-            Ok(Value::Object(map))
+    }
+}
+
+/// Read every input file, each in its own format, and deep-merge them left to right
+fn merge_inputs(files: &[PathBuf], cli: &Cli) -> Result<Value> {
+    let arrays = match cli.merge_arrays {
+        ArrayStrategy::Replace => merge::ArrayMerge::Replace,
+        ArrayStrategy::Append => merge::ArrayMerge::Append,
+        ArrayStrategy::MergeByKey => merge::ArrayMerge::ByKey(&cli.merge_key),
+    };
+
+    // Without files the only layer is stdin
+    let files: Vec<Option<PathBuf>> = if files.is_empty() {
+        vec![None]
+    } else {
+        files.iter().cloned().map(Some).collect()
+    };
+
+    let mut merged: Option<Value> = None;
+    for file in files {
+        let name = file.as_ref().map_or_else(|| "stdin".to_string(), |path| path.display().to_string());
+        let (_, _, layer) = load_input(&file, cli)
+            .context(format!("Failed to read {}", name))?;
+        match &mut merged {
+            Some(merged) => merge::deep_merge(merged, layer, arrays),
+            None => merged = Some(layer),
+        }
+    }
+    merged.context("No input to merge")
+}
+
+/// Compare two documents and print their differences keyed by normalized path
+///
+/// Exits with status 1 when the documents differ, like diff(1); `main` turns
+/// errors into status 2.
+fn run_diff(left: &Path, right: &Path, query: Option<&str>, json: bool, cli: &Cli) -> Result<()> {
+    let query = query.map(|query| parse_query_arg(query, cli.pointer)).transpose()?;
+
+    // Each side is parsed according to its own format
+    let mut documents = Vec::new();
+    for path in [left, right] {
+        let (_, _, document) = load_input(&Some(path.to_path_buf()), cli)
+            .context(format!("Failed to read {}", path.display()))?;
+        documents.push(match &query {
+            Some(query) => narrow(&document, query)
+                .context(format!("Failed to apply query to {}", path.display()))?,
+            None => (Vec::new(), document),
+        });
+    }
+
+    let (base, old) = &documents[0];
+    let (_, new) = &documents[1];
+    let changes = diff::diff(old, new, base);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff::to_json(&changes))
+            .context("Failed to serialize differences")?);
+    } else {
+        print!("{}", diff::to_text(&changes));
+    }
+
+    if !changes.is_empty() {
+        io::stdout().flush().context("Failed to write differences")?;
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Narrow a document with a query, keeping the location of a single matched node
+fn narrow(document: &Value, query: &parser::Query) -> Result<(Vec<engine::PathElement>, Value)> {
+    if query.is_singular() {
+        let found = engine::locate(document, query)?
+            .into_iter()
+            .next()
+            .context("Query did not match any value")?;
+        Ok((found.path, found.value.clone()))
+    } else {
+        Ok((Vec::new(), engine::apply_query(document, query)?))
+    }
+}
+
+/// Atomically replace a file with new contents
+///
+/// The contents are written to a temporary file in the same directory, synced
+/// to disk and renamed over the original, so readers see either the old or the
+/// new file and never a partial write. The original permissions are kept, and
+/// with `backup` a copy of the original is left next to it with that suffix.
+fn write_in_place(path: &Path, contents: &str, backup: Option<&str>) -> Result<()> {
+    // Replace the target of a symlink rather than the link itself
+    let path = fs::canonicalize(path)
+        .context(format!("Failed to resolve file: {}", path.display()))?;
+    let dir = path.parent().context("File has no parent directory")?;
+    let permissions = fs::metadata(&path)
+        .context(format!("Failed to read metadata of {}", path.display()))?
+        .permissions();
+
+    let mut temp = tempfile::Builder::new()
+        .prefix(".decode-")
+        .tempfile_in(dir)
+        .context(format!("Failed to create a temporary file in {}", dir.display()))?;
+    temp.write_all(contents.as_bytes())
+        .context("Failed to write temporary file")?;
+    temp.as_file().set_permissions(permissions)
+        .context("Failed to set permissions of temporary file")?;
+    temp.as_file().sync_all()
+        .context("Failed to sync temporary file")?;
+
+    if let Some(suffix) = backup {
+        let mut backup_path = path.clone().into_os_string();
+        backup_path.push(suffix);
+        fs::copy(&path, &backup_path)
+            .context(format!("Failed to write backup: {}", PathBuf::from(backup_path).display()))?;
+    }
+
+    temp.persist(&path)
+        .context(format!("Failed to replace {}", path.display()))?;
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .context(format!("Failed to sync directory {}", dir.display()))?;
+
+    Ok(())
+}
+
+/// Render every matched node on its own line, or NUL-terminated with `--nul`
+///
+/// With `--paths` or `--with-paths` the location of each match is included too.
+fn render_stream(matches: Vec<engine::Match>, cli: &Cli, palette: Option<&output::Palette>) -> Result<String> {
+    if !cli.paths && !matches!(cli.output, OutputFormat::Compact | OutputFormat::Raw) {
+        return Err(anyhow!("Printing one result per line only supports compact and raw output"));
+    }
+    let terminator = if cli.nul { '\0' } else { '\n' };
+
+    let mut text = String::new();
+    for found in matches {
+        let path = match cli.path_format {
+            PathFormat::Normalized => engine::normalized_path(&found.path),
+            PathFormat::Pointer => engine::json_pointer(&found.path),
+        };
+        if cli.paths {
+            text.push_str(&path);
+            text.push(terminator);
+            continue;
+        }
+
+        let mut node = found.value.clone();
+        if cli.sort_keys {
+            output::sort_keys(&mut node);
         }
+        let rendered = match (cli.output, palette) {
+            (OutputFormat::Raw, _) => output::to_raw(&node),
+            (_, Some(palette)) => output::to_colored_json(&node, palette, false),
+            _ => node.to_string(),
+        };
+        if cli.with_paths {
+            text.push_str(&path);
+            text.push('\t');
+        }
+        text.push_str(&rendered);
+        text.push(terminator);
+    }
+    Ok(text)
+}
+
+/// Decide whether to colourise output and with which palette
+fn color_palette(choice: ColorChoice) -> Result<Option<output::Palette>> {
+    let enabled = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            // See https://no-color.org: any non-empty NO_COLOR disables colour
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+            !no_color && io::stdout().is_terminal()
+        },
+    };
+    if !enabled {
+        return Ok(None);
+    }
+
+    match std::env::var("DECODE_COLORS") {
+        Ok(spec) => output::Palette::parse(&spec)
+            .context("Invalid DECODE_COLORS palette")
+            .map(Some),
+        Err(_) => Ok(Some(output::Palette::default())),
+    }
+}
+
+/// Read an input file (or stdin), detect its format and parse it
+///
+/// Returns the source text alongside the parsed document so that editing
+/// commands can preserve its formatting.
+fn load_input(file_path: &Option<PathBuf>, cli: &Cli) -> Result<(String, InputFormat, Value)> {
+    let content = read_input(file_path)?;
+    let format = match input::determine_input_format(file_path, cli.input_format, &content)? {
+        // Lenient JSON is read as JSONC, a superset of it
+        InputFormat::Json if cli.lenient => InputFormat::Jsonc,
+        format => format,
+    };
+    let options = input::InputOptions {
+        delimiter: cli.delimiter,
+        quote: cli.quote,
+        header: !cli.no_header,
+        infer_types: cli.infer_types,
+        xml_arrays: cli.xml_array.clone(),
+        expand_keys: cli.expand_keys,
+    };
+    let document = input::parse_bytes(&content, format, &options)?;
+    // Binary documents have no source text, and editing commands refuse them
+    let text = String::from_utf8(content).unwrap_or_default();
+    Ok((text, format, document))
+}
+
+/// Parse a single-byte character argument, accepting `tab` and `\t` for a tab
+fn parse_byte(arg: &str) -> Result<u8, String> {
+    match arg {
+        "tab" | "\\t" => Ok(b'\t'),
+        _ if arg.len() == 1 => Ok(arg.as_bytes()[0]),
+        _ => Err(format!("expected a single ASCII character, got '{}'", arg)),
+    }
+}
+
+/// Read input data from file or stdin, as bytes since it may be binary
+fn read_input(file_path: &Option<PathBuf>) -> Result<Vec<u8>> {
+    if let Some(path) = file_path {
+        // Read from file
+        let mut file = File::open(path)
+            .context(format!("Failed to open file: {}", path.display()))?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)
+            .context("Failed to read file")?;
+        Ok(content)
+    } else {
+        // Read from stdin
+        let mut buffer = Vec::new();
+        io::stdin().read_to_end(&mut buffer)
+            .context("Failed to read from stdin")?;
+        Ok(buffer)
     }
 }
@@ -0,0 +1,46 @@
+use serde_json::Value;
+
+/// How arrays found at the same location in two documents are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayMerge<'a> {
+    /// The later array replaces the earlier one
+    Replace,
+    /// The elements of the later array are appended to the earlier one
+    Append,
+    /// Objects with the same value for this key are merged, other elements appended
+    ByKey(&'a str),
+}
+
+/// Deep-merge `overlay` into `base`
+///
+/// Objects are merged key by key, arrays according to `arrays`, and any other
+/// value in the overlay (including null) replaces the one in the base.
+pub fn deep_merge(base: &mut Value, overlay: Value, arrays: ArrayMerge) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value, arrays),
+                    None => {
+                        base.insert(key, value);
+                    },
+                }
+            }
+        },
+        (Value::Array(base), Value::Array(overlay)) => match arrays {
+            ArrayMerge::Replace => *base = overlay,
+            ArrayMerge::Append => base.extend(overlay),
+            ArrayMerge::ByKey(key) => {
+                for item in overlay {
+                    let existing = item.get(key)
+                        .and_then(|id| base.iter_mut().find(|candidate| candidate.get(key) == Some(id)));
+                    match existing {
+                        Some(existing) => deep_merge(existing, item, arrays),
+                        None => base.push(item),
+                    }
+                }
+            },
+        },
+        (base, overlay) => *base = overlay,
+    }
+}
@@ -0,0 +1,522 @@
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Recursively sort the keys of every object in a value
+pub fn sort_keys(value: &mut Value) {
+    match value {
+        Value::Object(obj) => {
+            obj.sort_keys();
+            obj.values_mut().for_each(sort_keys);
+        },
+        Value::Array(arr) => arr.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+/// Render a value for raw output: scalars without JSON quoting, anything else as compact JSON
+pub fn to_raw(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// Render a result for raw output, writing array elements separated by `separator`
+///
+/// Returns `None` for an empty array, which produces no output at all.
+pub fn to_raw_joined(value: &Value, separator: &str) -> Option<String> {
+    match value {
+        Value::Array(arr) if arr.is_empty() => None,
+        Value::Array(arr) => Some(arr.iter().map(to_raw).collect::<Vec<_>>().join(separator)),
+        _ => Some(to_raw(value)),
+    }
+}
+
+/// Render a query result as a TOML document
+pub fn to_toml(value: &Value) -> Result<String> {
+    // A TOML document is always a table, so only objects can be rendered
+    let table = match json_to_toml_value(value, "$")? {
+        toml::Value::Table(table) => table,
+        _ => return Err(anyhow!(
+            "TOML output requires an object result, got {}", value_kind(value)
+        )),
+    };
+
+    // The serializer picks tables, arrays of tables or inline tables as needed
+    toml::to_string(&table)
+        .map_err(|e| anyhow!("Failed to serialize result as TOML: {}", e))
+}
+
+/// Encode a query result as a MessagePack document
+pub fn to_msgpack(value: &Value) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    rmpv::encode::write_value(&mut bytes, &json_to_msgpack(value))
+        .map_err(|e| anyhow!("Failed to serialize result as MessagePack: {}", e))?;
+    Ok(bytes)
+}
+
+fn json_to_msgpack(value: &Value) -> rmpv::Value {
+    match value {
+        Value::Null => rmpv::Value::Nil,
+        Value::Bool(b) => rmpv::Value::Boolean(*b),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                rmpv::Value::from(i)
+            } else if let Some(u) = n.as_u64() {
+                rmpv::Value::from(u)
+            } else {
+                rmpv::Value::F64(n.as_f64().unwrap_or_default())
+            }
+        },
+        Value::String(s) => rmpv::Value::from(s.as_str()),
+        Value::Array(arr) => rmpv::Value::Array(arr.iter().map(json_to_msgpack).collect()),
+        Value::Object(obj) => rmpv::Value::Map(
+            obj.iter().map(|(key, value)| (rmpv::Value::from(key.as_str()), json_to_msgpack(value))).collect()
+        ),
+    }
+}
+
+/// Encode a query result as a CBOR document
+pub fn to_cbor(value: &Value) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    ciborium::into_writer(value, &mut bytes)
+        .map_err(|e| anyhow!("Failed to serialize result as CBOR: {}", e))?;
+    Ok(bytes)
+}
+
+/// Convert a JSON value to a TOML value, reporting the location of anything unrepresentable
+fn json_to_toml_value(value: &Value, location: &str) -> Result<toml::Value> {
+    match value {
+        Value::Null => Err(anyhow!("TOML cannot represent null value at {}", location)),
+        Value::Bool(b) => Ok(toml::Value::Boolean(*b)),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Ok(toml::Value::Integer(i))
+            } else if n.is_u64() {
+                Err(anyhow!("TOML cannot represent integer {} at {} (out of range)", n, location))
+            } else if let Some(f) = n.as_f64() {
+                Ok(toml::Value::Float(f))
+            } else {
+                Err(anyhow!("TOML cannot represent number {} at {}", n, location))
+            }
+        },
+        Value::String(s) => Ok(toml::Value::String(s.clone())),
+        Value::Array(arr) => {
+            let mut toml_array = Vec::new();
+            for (idx, item) in arr.iter().enumerate() {
+                toml_array.push(json_to_toml_value(item, &format!("{}[{}]", location, idx))?);
+            }
+            Ok(toml::Value::Array(toml_array))
+        },
+        Value::Object(obj) => {
+            let mut table = toml::map::Map::new();
+            for (key, item) in obj {
+                table.insert(key.clone(), json_to_toml_value(item, &format!("{}['{}']", location, key))?);
+            }
+            Ok(toml::Value::Table(table))
+        }
+    }
+}
+
+/// Describe the type of a JSON value for error messages
+fn value_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Render a query result as delimiter-separated rows (CSV or TSV)
+///
+/// Each object becomes a row. The header is either the explicit `columns` list
+/// or the union of all keys in the order they are first seen. Nested values are
+/// flattened into `parent<sep>child` columns when a separator is given, and
+/// JSON-encoded otherwise.
+pub fn to_delimited(
+    value: &Value,
+    delimiter: u8,
+    columns: Option<&[String]>,
+    flatten_separator: Option<&str>,
+) -> Result<String> {
+    let rows: Vec<&Value> = match value {
+        Value::Array(arr) => arr.iter().collect(),
+        Value::Object(_) => vec![value],
+        _ => return Err(anyhow!(
+            "CSV/TSV output requires an array of objects, got {}", value_kind(value)
+        )),
+    };
+
+    // Turn every row into a flat list of (column, cell) pairs
+    let mut records = Vec::new();
+    for (idx, row) in rows.iter().enumerate() {
+        let Value::Object(obj) = row else {
+            return Err(anyhow!(
+                "CSV/TSV output requires an array of objects, element {} is {}",
+                idx, value_kind(row)
+            ));
+        };
+        let mut record = Vec::new();
+        for (key, item) in obj {
+            flatten_cell(key.clone(), item, flatten_separator, &mut record);
+        }
+        records.push(record);
+    }
+
+    // Use the explicit column list or derive one from the union of keys
+    let header: Vec<String> = match columns {
+        Some(columns) => columns.to_vec(),
+        None => union_of_keys(&records),
+    };
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(Vec::new());
+    writer.write_record(&header)?;
+    for record in &records {
+        let cells = header.iter().map(|column| {
+            record.iter()
+                .find(|(name, _)| name == column)
+                .map(|(_, cell)| cell.as_str())
+                .unwrap_or("")
+        });
+        writer.write_record(cells)?;
+    }
+
+    let bytes = writer.into_inner()
+        .map_err(|e| anyhow!("Failed to write delimited output: {}", e))?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Collect every column name in the order it is first seen across records
+fn union_of_keys(records: &[Vec<(String, String)>]) -> Vec<String> {
+    let mut header: Vec<String> = Vec::new();
+    for record in records {
+        for (column, _) in record {
+            if !header.contains(column) {
+                header.push(column.clone());
+            }
+        }
+    }
+    header
+}
+
+/// Flatten a single value into one or more (column, cell) pairs
+fn flatten_cell(column: String, value: &Value, separator: Option<&str>, out: &mut Vec<(String, String)>) {
+    match (value, separator) {
+        (Value::Object(obj), Some(sep)) if !obj.is_empty() => {
+            for (key, item) in obj {
+                flatten_cell(format!("{}{}{}", column, sep, key), item, separator, out);
+            }
+        },
+        (Value::Array(arr), Some(sep)) if !arr.is_empty() => {
+            for (idx, item) in arr.iter().enumerate() {
+                flatten_cell(format!("{}{}{}", column, sep, idx), item, separator, out);
+            }
+        },
+        _ => out.push((column, scalar_cell(value))),
+    }
+}
+
+/// Render a value as the text of a single cell
+fn scalar_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        // Nested structures that are not flattened are JSON-encoded
+        _ => value.to_string(),
+    }
+}
+
+/// Render a query result as an aligned, human-readable table
+///
+/// Arrays of objects get one column per key, a single object is shown as
+/// key/value pairs and anything else as a single `value` column. Nested
+/// structures are abbreviated, and columns are shrunk to fit `max_width`
+/// when one is given.
+pub fn to_table(
+    value: &Value,
+    columns: Option<&[String]>,
+    ascii: bool,
+    max_width: Option<usize>,
+) -> String {
+    let (header, rows) = table_rows(value, columns);
+    let style = if ascii { &ASCII_TABLE } else { &UNICODE_TABLE };
+
+    // Start from the natural width of each column
+    let mut widths: Vec<usize> = header.iter().map(|h| h.width()).collect();
+    for row in &rows {
+        for (idx, cell) in row.iter().enumerate() {
+            widths[idx] = widths[idx].max(cell.width());
+        }
+    }
+
+    // Shrink the widest column until the table fits, one character at a time
+    if let Some(max_width) = max_width {
+        let borders = 3 * widths.len() + 1;
+        while widths.iter().sum::<usize>() + borders > max_width {
+            let (idx, &widest) = match widths.iter().enumerate().max_by_key(|(_, w)| **w) {
+                Some(widest) => widest,
+                None => break,
+            };
+            if widest <= MIN_COLUMN_WIDTH {
+                break;
+            }
+            widths[idx] -= 1;
+        }
+    }
+
+    let mut out = String::new();
+    out.push_str(&table_rule(&widths, style.top, style));
+    out.push_str(&table_line(&header, &widths, style));
+    out.push_str(&table_rule(&widths, style.middle, style));
+    for row in &rows {
+        out.push_str(&table_line(row, &widths, style));
+    }
+    out.push_str(&table_rule(&widths, style.bottom, style));
+    out
+}
+
+/// Narrowest a column is allowed to become when fitting the terminal width
+const MIN_COLUMN_WIDTH: usize = 3;
+
+/// Characters used to draw a table
+struct TableStyle {
+    horizontal: char,
+    vertical: char,
+    /// Left, junction and right characters for the top, middle and bottom rules
+    top: [char; 3],
+    middle: [char; 3],
+    bottom: [char; 3],
+    ellipsis: &'static str,
+}
+
+const UNICODE_TABLE: TableStyle = TableStyle {
+    horizontal: '─',
+    vertical: '│',
+    top: ['┌', '┬', '┐'],
+    middle: ['├', '┼', '┤'],
+    bottom: ['└', '┴', '┘'],
+    ellipsis: "…",
+};
+
+const ASCII_TABLE: TableStyle = TableStyle {
+    horizontal: '-',
+    vertical: '|',
+    top: ['+', '+', '+'],
+    middle: ['+', '+', '+'],
+    bottom: ['+', '+', '+'],
+    ellipsis: "~",
+};
+
+/// Build the header and the cell text of every row
+fn table_rows(value: &Value, columns: Option<&[String]>) -> (Vec<String>, Vec<Vec<String>>) {
+    match value {
+        Value::Array(arr) if !arr.is_empty() && arr.iter().all(Value::is_object) => {
+            let header: Vec<String> = match columns {
+                Some(columns) => columns.to_vec(),
+                None => {
+                    let records: Vec<Vec<(String, String)>> = arr.iter()
+                        .filter_map(Value::as_object)
+                        .map(|obj| obj.keys().map(|k| (k.clone(), String::new())).collect())
+                        .collect();
+                    union_of_keys(&records)
+                }
+            };
+            let rows = arr.iter()
+                .map(|row| header.iter()
+                    .map(|column| row.get(column).map(abbreviated_cell).unwrap_or_default())
+                    .collect())
+                .collect();
+            (header, rows)
+        },
+        Value::Object(obj) => {
+            let rows = obj.iter()
+                .map(|(key, item)| vec![key.clone(), abbreviated_cell(item)])
+                .collect();
+            (vec!["key".to_string(), "value".to_string()], rows)
+        },
+        Value::Array(arr) => {
+            let rows = arr.iter().map(|item| vec![abbreviated_cell(item)]).collect();
+            (vec!["value".to_string()], rows)
+        },
+        _ => (vec!["value".to_string()], vec![vec![abbreviated_cell(value)]]),
+    }
+}
+
+/// Render a value for a table cell, summarising nested structures
+fn abbreviated_cell(value: &Value) -> String {
+    match value {
+        Value::Object(obj) => match obj.len() {
+            1 => "{1 key}".to_string(),
+            n => format!("{{{} keys}}", n),
+        },
+        Value::Array(arr) => match arr.len() {
+            1 => "[1 item]".to_string(),
+            n => format!("[{} items]", n),
+        },
+        // Keep every row on a single line
+        Value::String(s) => s.replace('\n', "\\n").replace('\t', "\\t").replace('\r', "\\r"),
+        Value::Null => "null".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// Draw a horizontal rule using the given left/junction/right characters
+fn table_rule(widths: &[usize], corners: [char; 3], style: &TableStyle) -> String {
+    let segments: Vec<String> = widths.iter()
+        .map(|w| style.horizontal.to_string().repeat(w + 2))
+        .collect();
+    format!("{}{}{}\n", corners[0], segments.join(&corners[1].to_string()), corners[2])
+}
+
+/// Draw a row of cells, padding or truncating each one to its column width
+fn table_line(cells: &[String], widths: &[usize], style: &TableStyle) -> String {
+    let mut line = style.vertical.to_string();
+    for (cell, &width) in cells.iter().zip(widths) {
+        let text = truncate_to_width(cell, width, style.ellipsis);
+        let padding = width - text.width();
+        line.push_str(&format!(" {}{} {}", text, " ".repeat(padding), style.vertical));
+    }
+    line.push('\n');
+    line
+}
+
+/// Cut a string down to `width` display columns, marking the cut with an ellipsis
+fn truncate_to_width(text: &str, width: usize, ellipsis: &str) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let budget = width.saturating_sub(ellipsis.width());
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > budget {
+            break;
+        }
+        used += w;
+        out.push(c);
+    }
+    out.push_str(ellipsis);
+    out
+}
+
+/// ANSI SGR sequences used to colour each kind of JSON token
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub key: String,
+    pub string: String,
+    pub number: String,
+    pub boolean: String,
+    pub null: String,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            key: "1;34".to_string(),
+            string: "32".to_string(),
+            number: "36".to_string(),
+            boolean: "33".to_string(),
+            null: "90".to_string(),
+        }
+    }
+}
+
+impl Palette {
+    /// Parse a palette specification such as `key=1;34:string=32:null=2`
+    ///
+    /// Tokens that are not mentioned keep their default colour.
+    pub fn parse(spec: &str) -> Result<Palette> {
+        let mut palette = Palette::default();
+        for entry in spec.split(':').filter(|e| !e.is_empty()) {
+            let (name, sgr) = entry.split_once('=')
+                .ok_or_else(|| anyhow!("Invalid colour entry '{}', expected NAME=SGR", entry))?;
+            if !sgr.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                return Err(anyhow!("Invalid colour code '{}' for '{}'", sgr, name));
+            }
+            let slot = match name {
+                "key" => &mut palette.key,
+                "string" => &mut palette.string,
+                "number" => &mut palette.number,
+                "bool" | "boolean" => &mut palette.boolean,
+                "null" => &mut palette.null,
+                _ => return Err(anyhow!(
+                    "Unknown colour name '{}' (expected key, string, number, bool or null)", name
+                )),
+            };
+            *slot = sgr.to_string();
+        }
+        Ok(palette)
+    }
+}
+
+/// Render a JSON value with ANSI colours, either pretty-printed or compact
+///
+/// The layout matches `serde_json::to_string_pretty` and `serde_json::to_string`.
+pub fn to_colored_json(value: &Value, palette: &Palette, pretty: bool) -> String {
+    let mut out = String::new();
+    write_colored(value, palette, pretty, 0, &mut out);
+    out
+}
+
+/// Append a coloured JSON value to `out` at the given nesting depth
+fn write_colored(value: &Value, palette: &Palette, pretty: bool, depth: usize, out: &mut String) {
+    match value {
+        Value::Null => paint(&palette.null, "null", out),
+        Value::Bool(b) => paint(&palette.boolean, &b.to_string(), out),
+        Value::Number(n) => paint(&palette.number, &n.to_string(), out),
+        Value::String(_) => paint(&palette.string, &value.to_string(), out),
+        Value::Array(arr) if arr.is_empty() => out.push_str("[]"),
+        Value::Object(obj) if obj.is_empty() => out.push_str("{}"),
+        Value::Array(arr) => {
+            out.push('[');
+            for (idx, item) in arr.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                newline_indent(pretty, depth + 1, out);
+                write_colored(item, palette, pretty, depth + 1, out);
+            }
+            newline_indent(pretty, depth, out);
+            out.push(']');
+        },
+        Value::Object(obj) => {
+            out.push('{');
+            for (idx, (key, item)) in obj.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                newline_indent(pretty, depth + 1, out);
+                paint(&palette.key, &Value::String(key.clone()).to_string(), out);
+                out.push_str(if pretty { ": " } else { ":" });
+                write_colored(item, palette, pretty, depth + 1, out);
+            }
+            newline_indent(pretty, depth, out);
+            out.push('}');
+        },
+    }
+}
+
+/// Wrap `text` in an ANSI colour sequence
+fn paint(sgr: &str, text: &str, out: &mut String) {
+    out.push_str(&format!("\x1b[{}m{}\x1b[0m", sgr, text));
+}
+
+/// Start a new line at the given depth when pretty-printing
+fn newline_indent(pretty: bool, depth: usize, out: &mut String) {
+    if pretty {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+}
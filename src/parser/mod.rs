@@ -15,6 +15,15 @@ pub enum PathSegment {
     MultiIndex(Vec<i64>),
     Filter(FilterExpression),
     RecursiveWildcard,
+    /// A JSON Pointer reference token: a member name on objects, an index on arrays
+    Token(String),
+}
+
+impl PathSegment {
+    /// Whether this segment selects at most one node from a single input node
+    pub fn is_singular(&self) -> bool {
+        matches!(self, PathSegment::Field(_) | PathSegment::Index(_) | PathSegment::Token(_))
+    }
 }
 
 /// Represents a filter expression with path, operator and value
@@ -52,6 +61,13 @@ pub struct Query {
     pub recursive_paths: Vec<Vec<PathSegment>>,
 }
 
+impl Query {
+    /// Whether the query can only ever match a single node
+    pub fn is_singular(&self) -> bool {
+        self.recursive_paths.is_empty() && self.path_segments.iter().all(PathSegment::is_singular)
+    }
+}
+
 /// Parses a query string into a structured Query object
 pub fn parse_query(input: &str) -> Result<Query> {
     // Parse the input using the pest parser
@@ -90,6 +106,42 @@ pub fn parse_query(input: &str) -> Result<Query> {
     })
 }
 
+/// Parses an RFC 6901 JSON Pointer such as `/store/book/0/title` into a Query
+pub fn parse_pointer(input: &str) -> Result<Query> {
+    // The empty pointer refers to the whole document
+    if input.is_empty() {
+        return Ok(Query { path_segments: Vec::new(), recursive_paths: Vec::new() });
+    }
+    let tokens = input.strip_prefix('/')
+        .context(format!("JSON Pointer must start with '/': {}", input))?;
+
+    let mut path_segments = Vec::new();
+    for token in tokens.split('/') {
+        // A single left-to-right pass decodes "~01" as "~1" rather than "/"
+        let mut unescaped = String::new();
+        let mut chars = token.chars();
+        while let Some(c) = chars.next() {
+            if c == '~' {
+                match chars.next() {
+                    Some('0') => unescaped.push('~'),
+                    Some('1') => unescaped.push('/'),
+                    _ => return Err(anyhow::anyhow!(
+                        "Invalid escape in JSON Pointer token '{}' (expected ~0 or ~1)", token
+                    )),
+                }
+            } else {
+                unescaped.push(c);
+            }
+        }
+        path_segments.push(PathSegment::Token(unescaped));
+    }
+
+    Ok(Query {
+        path_segments,
+        recursive_paths: Vec::new(),
+    })
+}
+
 /// Parse a path into individual path segments
 fn parse_path_segments(pairs: Pairs<Rule>) -> Result<Vec<PathSegment>> {
     let mut segments = Vec::new();
@@ -1,7 +1,13 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use crate::parser;
     use crate::engine;
+    use crate::edit;
+    use crate::diff;
+    use crate::merge;
+    use crate::input::{self, InputFormat};
+    use crate::output;
     use serde_json::json;
 
     #[test]
@@ -157,4 +163,833 @@ mod tests {
         assert_eq!(result.as_array().unwrap().len(), 1);
         assert_eq!(result.as_array().unwrap()[0]["name"], "Bob");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_toml_output() {
+        let json = json!({
+            "package": {"name": "decode", "version": "0.1.0"},
+            "bin": [{"name": "decode"}, {"name": "other"}]
+        });
+
+        let rendered = output::to_toml(&json).unwrap();
+        let parsed: toml::Value = toml::from_str(&rendered).unwrap();
+
+        assert_eq!(parsed["package"]["version"].as_str(), Some("0.1.0"));
+        assert_eq!(parsed["bin"].as_array().unwrap().len(), 2);
+        assert!(rendered.contains("[[bin]]"));
+    }
+
+    #[test]
+    fn test_toml_output_rejects_unrepresentable_values() {
+        assert!(output::to_toml(&json!("decode")).is_err());
+        assert!(output::to_toml(&json!([1, 2])).is_err());
+
+        let err = output::to_toml(&json!({"a": {"b": null}})).unwrap_err();
+        assert!(err.to_string().contains("$['a']['b']"));
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let json = json!([
+            {"name": "Alice", "note": "says \"hi\", twice"},
+            {"name": "Bob", "age": 30, "tags": ["a", "b"]}
+        ]);
+
+        let rendered = output::to_delimited(&json, b',', None, None).unwrap();
+
+        assert_eq!(rendered, "name,note,age,tags\n\
+                              Alice,\"says \"\"hi\"\", twice\",,\n\
+                              Bob,,30,\"[\"\"a\"\",\"\"b\"\"]\"\n");
+    }
+
+    #[test]
+    fn test_tsv_output_with_columns_and_flatten() {
+        let json = json!([
+            {"name": {"first": "John", "last": "Doe"}, "age": 30},
+            {"name": {"first": "Jane", "last": "Smith"}, "age": 25}
+        ]);
+        let columns = vec!["name.first".to_string(), "age".to_string()];
+
+        let rendered = output::to_delimited(&json, b'\t', Some(&columns), Some(".")).unwrap();
+
+        assert_eq!(rendered, "name.first\tage\nJohn\t30\nJane\t25\n");
+    }
+
+    #[test]
+    fn test_table_output() {
+        let json = json!([
+            {"title": "The Rust Programming Language", "tags": ["rust", "programming"]},
+            {"title": "Neuromancer", "year": 1984}
+        ]);
+
+        let rendered = output::to_table(&json, None, true, Some(30));
+
+        assert_eq!(rendered, "\
++----------+----------+------+
+| title    | tags     | year |
++----------+----------+------+
+| The Rus~ | [2 item~ |      |
+| Neuroma~ |          | 1984 |
++----------+----------+------+
+");
+    }
+
+    #[test]
+    fn test_colored_json_matches_plain_layout() {
+        let json = json!({"name": "Alice", "tags": ["a\"b", 1, true, null], "empty": {}, "none": []});
+        let palette = output::Palette::default();
+
+        for pretty in [true, false] {
+            let colored = output::to_colored_json(&json, &palette, pretty);
+            let mut stripped = String::new();
+            let mut chars = colored.chars();
+            while let Some(c) = chars.next() {
+                if c == '\x1b' {
+                    // Skip the escape sequence up to and including its final 'm'
+                    for c in chars.by_ref() {
+                        if c == 'm' {
+                            break;
+                        }
+                    }
+                } else {
+                    stripped.push(c);
+                }
+            }
+            let plain = if pretty {
+                serde_json::to_string_pretty(&json).unwrap()
+            } else {
+                serde_json::to_string(&json).unwrap()
+            };
+            assert_eq!(stripped, plain);
+        }
+
+        let colored = output::to_colored_json(&json!({"k": 1}), &palette, false);
+        assert_eq!(colored, "{\x1b[1;34m\"k\"\x1b[0m:\x1b[36m1\x1b[0m}");
+    }
+
+    #[test]
+    fn test_palette_parse() {
+        let palette = output::Palette::parse("key=31:null=2;37").unwrap();
+        assert_eq!(palette.key, "31");
+        assert_eq!(palette.null, "2;37");
+        assert_eq!(palette.string, output::Palette::default().string);
+
+        assert!(output::Palette::parse("keys=31").is_err());
+        assert!(output::Palette::parse("key=red").is_err());
+        assert!(output::Palette::parse("key").is_err());
+    }
+
+    #[test]
+    fn test_wildcard_preserves_key_order() {
+        let json: serde_json::Value = serde_json::from_str(r#"{"zeta": 1, "alpha": 2, "mid": 3}"#).unwrap();
+
+        let query = parser::parse_query("$[*]").unwrap();
+        let result = engine::apply_query(&json, &query).unwrap();
+
+        assert_eq!(result, json!([1, 2, 3]));
+        assert_eq!(serde_json::to_string(&json).unwrap(), r#"{"zeta":1,"alpha":2,"mid":3}"#);
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let mut json = json!({"b": {"z": 1, "a": 2}, "a": [{"y": 1, "x": 2}]});
+
+        output::sort_keys(&mut json);
+
+        assert_eq!(serde_json::to_string(&json).unwrap(), r#"{"a":[{"x":2,"y":1}],"b":{"a":2,"z":1}}"#);
+    }
+
+    #[test]
+    fn test_segments_apply_to_each_match() {
+        let json = json!({
+            "users": [
+                {"name": "Alice", "age": 25},
+                {"name": "Bob", "age": 30},
+                {"nickname": "C", "age": 35}
+            ],
+            "matrix": [[1, 2], [3, 4]]
+        });
+
+        let query = parser::parse_query("$.users[*].name").unwrap();
+        assert_eq!(engine::apply_query(&json, &query).unwrap(), json!(["Alice", "Bob"]));
+
+        let query = parser::parse_query("$.matrix[*][0]").unwrap();
+        assert_eq!(engine::apply_query(&json, &query).unwrap(), json!([1, 3]));
+
+        // Matches a later segment doesn't apply to are skipped rather than failing the query
+        let query = parser::parse_query("$.users[?(@.age > 28)].name").unwrap();
+        assert_eq!(engine::apply_query(&json, &query).unwrap(), json!(["Bob"]));
+
+        let query = parser::parse_query("$.users[?(@.age > 28)][0]").unwrap();
+        assert_eq!(engine::apply_query(&json, &query).unwrap(), json!([]));
+
+        // Missing fields are still an error on a singular path
+        let query = parser::parse_query("$.users[2].name").unwrap();
+        assert!(engine::apply_query(&json, &query).is_err());
+
+        // A recursive descent that finds nothing matches nothing
+        let query = parser::parse_query("$..missing").unwrap();
+        assert_eq!(engine::apply_query(&json, &query).unwrap(), json!([]));
+    }
+
+    #[test]
+    fn test_select_returns_each_match() {
+        let json = json!({
+            "store": {
+                "book": [
+                    {"title": "Dune", "price": 9.99},
+                    {"title": "Emma", "price": 5.5}
+                ],
+                "departments": ["Books", "Games"]
+            }
+        });
+
+        let query = parser::parse_query("$..price").unwrap();
+        let nodes = engine::select(&json, &query).unwrap();
+        assert_eq!(nodes, vec![&json!(9.99), &json!(5.5)]);
+
+        // A singular query yields a single node even when it is an array
+        let query = parser::parse_query("$.store.departments").unwrap();
+        let nodes = engine::select(&json, &query).unwrap();
+        assert_eq!(nodes, vec![&json!(["Books", "Games"])]);
+    }
+
+    #[test]
+    fn test_raw_output_joins_array_elements() {
+        let json = json!(["Books", "multi\nline", 3, null, {"a": 1}]);
+
+        assert_eq!(output::to_raw_joined(&json, "\n").unwrap(), "Books\nmulti\nline\n3\nnull\n{\"a\":1}");
+        assert_eq!(output::to_raw_joined(&json!(["a", "b"]), ",").unwrap(), "a,b");
+        assert_eq!(output::to_raw_joined(&json!("plain"), ",").unwrap(), "plain");
+        assert_eq!(output::to_raw_joined(&json!([]), ","), None);
+    }
+
+    #[test]
+    fn test_locate_normalized_paths() {
+        let json = json!({
+            "store": {
+                "book": [
+                    {"title": "Dune", "price": 9.99},
+                    {"title": "Emma", "price": 5.5},
+                    {"title": "Ulysses", "price": 12.0}
+                ]
+            }
+        });
+
+        let query = parser::parse_query("$.store.book[?(@.price > 9)].title").unwrap();
+        let paths: Vec<String> = engine::locate(&json, &query).unwrap()
+            .iter()
+            .map(|m| engine::normalized_path(&m.path))
+            .collect();
+        assert_eq!(paths, vec![
+            "$['store']['book'][0]['title']",
+            "$['store']['book'][2]['title']",
+        ]);
+
+        let query = parser::parse_query("$.store.book[-1].price").unwrap();
+        let found = engine::locate(&json, &query).unwrap();
+        assert_eq!(engine::normalized_path(&found[0].path), "$['store']['book'][2]['price']");
+        assert_eq!(found[0].value, &json!(12.0));
+    }
+
+    #[test]
+    fn test_normalized_path_escaping() {
+        let path = vec![
+            engine::PathElement::Key("it's".to_string()),
+            engine::PathElement::Key("a\\b\n".to_string()),
+            engine::PathElement::Index(3),
+        ];
+
+        assert_eq!(engine::normalized_path(&path), r"$['it\'s']['a\\b\n'][3]");
+    }
+
+    #[test]
+    fn test_json_pointer_query() {
+        let json = json!({
+            "store": {"book": [{"title": "Dune"}, {"title": "Emma"}]},
+            "a/b": {"m~n": 1},
+            "10": "ten"
+        });
+
+        let query = parser::parse_pointer("/store/book/1/title").unwrap();
+        assert_eq!(engine::apply_query(&json, &query).unwrap(), json!("Emma"));
+
+        let query = parser::parse_pointer("/a~1b/m~0n").unwrap();
+        assert_eq!(engine::apply_query(&json, &query).unwrap(), json!(1));
+
+        // Numeric tokens are member names on objects
+        let query = parser::parse_pointer("/10").unwrap();
+        assert_eq!(engine::apply_query(&json, &query).unwrap(), json!("ten"));
+
+        let query = parser::parse_pointer("").unwrap();
+        assert_eq!(engine::apply_query(&json, &query).unwrap(), json);
+
+        let query = parser::parse_pointer("/store/book/01").unwrap();
+        assert!(engine::apply_query(&json, &query).is_err());
+        assert!(parser::parse_pointer("/bad~2escape").is_err());
+        assert!(parser::parse_pointer("store").is_err());
+    }
+
+    #[test]
+    fn test_json_pointer_path_format() {
+        let path = vec![
+            engine::PathElement::Key("a/b".to_string()),
+            engine::PathElement::Key("m~n".to_string()),
+            engine::PathElement::Index(0),
+        ];
+
+        assert_eq!(engine::json_pointer(&path), "/a~1b/m~0n/0");
+        assert_eq!(engine::json_pointer(&[]), "");
+    }
+
+    /// Plan and apply a set, returning the number of operations
+    fn set(json: &mut serde_json::Value, query: &parser::Query, value: &serde_json::Value, create: bool) -> anyhow::Result<usize> {
+        let operations = edit::plan_set(json, query, value, create)?;
+        edit::apply(json, &operations)?;
+        Ok(operations.len())
+    }
+
+    /// Plan and apply a delete, returning the number of operations
+    fn delete(json: &mut serde_json::Value, query: &parser::Query) -> anyhow::Result<usize> {
+        let operations = edit::plan_delete(json, query)?;
+        edit::apply(json, &operations)?;
+        Ok(operations.len())
+    }
+
+    #[test]
+    fn test_set_every_match() {
+        let mut json = json!({
+            "package": {"name": "decode", "version": "0.1.0"},
+            "items": [{"price": 1}, {"price": 2}, {"name": "no price"}]
+        });
+
+        let query = parser::parse_query("$.package.version").unwrap();
+        assert_eq!(set(&mut json, &query, &json!("0.2.0"), false).unwrap(), 1);
+        assert_eq!(json["package"]["version"], json!("0.2.0"));
+
+        let query = parser::parse_query("$.items[*].price").unwrap();
+        assert_eq!(set(&mut json, &query, &json!(0), false).unwrap(), 2);
+        assert_eq!(json["items"], json!([{"price": 0}, {"price": 0}, {"name": "no price"}]));
+    }
+
+    #[test]
+    fn test_set_creates_missing_objects() {
+        let mut json = json!({"server": {"host": "example.com"}});
+
+        let query = parser::parse_query("$.server.tls.cert").unwrap();
+        assert!(set(&mut json, &query, &json!("cert.pem"), false).is_err());
+        assert_eq!(set(&mut json, &query, &json!("cert.pem"), true).unwrap(), 1);
+        assert_eq!(json, json!({"server": {"host": "example.com", "tls": {"cert": "cert.pem"}}}));
+
+        let query = parser::parse_pointer("/server/host/port").unwrap();
+        assert!(set(&mut json, &query, &json!(1), true).is_err());
+
+        // Pointer tokens with leading zeros are not array indices
+        let mut json = json!({"a": [{"b": 1}, {"b": 2}]});
+        let query = parser::parse_pointer("/a/01/c").unwrap();
+        assert!(set(&mut json, &query, &json!(3), true).is_err());
+        let query = parser::parse_pointer("/a/1/c").unwrap();
+        assert_eq!(set(&mut json, &query, &json!(3), true).unwrap(), 1);
+        assert_eq!(json["a"][1], json!({"b": 2, "c": 3}));
+    }
+
+    #[test]
+    fn test_set_rejects_partly_parsed_query() {
+        use clap::Parser;
+        let text = "{\"a\": {\"b\": 1}}";
+        let document = json!({"a": {"b": 1}});
+
+        // A query is never shortened to the part that parses, which would edit the wrong node
+        for query in ["$.a[", "$.a garbage"] {
+            let cli = crate::Cli::try_parse_from(["decode", "set", query, "2"]).unwrap();
+            let command = cli.command.as_ref().unwrap();
+            assert!(crate::run_command(command, text, &document, InputFormat::Json, &cli).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_delete_matches() {
+        let mut json = json!({
+            "db": {"user": "admin", "password": "s3cr3t"},
+            "items": [0, 1, 2, 3, 4],
+            "nested": [{"secret": 1, "keep": {"secret": 2}}]
+        });
+
+        let query = parser::parse_query("$.db.password").unwrap();
+        assert_eq!(delete(&mut json, &query).unwrap(), 1);
+        assert_eq!(json["db"], json!({"user": "admin"}));
+
+        // Indices are removed from highest to lowest so they all stay valid
+        let query = parser::parse_query("$.items[0,2,4]").unwrap();
+        assert_eq!(delete(&mut json, &query).unwrap(), 3);
+        assert_eq!(json["items"], json!([1, 3]));
+
+        let query = parser::parse_query("$..secret").unwrap();
+        assert_eq!(delete(&mut json, &query).unwrap(), 2);
+        assert_eq!(json["nested"], json!([{"keep": {}}]));
+
+        let query = parser::parse_pointer("").unwrap();
+        assert!(delete(&mut json, &query).is_err());
+    }
+
+    #[test]
+    fn test_toml_edit_preserves_comments() {
+        let text = "# Package metadata\n[package]\nname = \"decode\"  # binary name\nversion = \"0.1.0\"\n\n[dependencies]\nanyhow = \"1.0\"\n";
+        let document: serde_json::Value = toml::from_str(text).unwrap();
+
+        let query = parser::parse_query("$.package.version").unwrap();
+        let operations = edit::plan_set(&document, &query, &json!("0.2.0"), false).unwrap();
+        assert_eq!(
+            edit::apply_to_toml(text, &operations).unwrap(),
+            "# Package metadata\n[package]\nname = \"decode\"  # binary name\nversion = \"0.2.0\"\n\n[dependencies]\nanyhow = \"1.0\"\n"
+        );
+
+        let query = parser::parse_query("$.dependencies.anyhow").unwrap();
+        let operations = edit::plan_delete(&document, &query).unwrap();
+        assert_eq!(
+            edit::apply_to_toml(text, &operations).unwrap(),
+            "# Package metadata\n[package]\nname = \"decode\"  # binary name\nversion = \"0.1.0\"\n\n[dependencies]\n"
+        );
+
+        // A table replaced by a plain value keeps the comment above its header
+        let query = parser::parse_query("$.package").unwrap();
+        let operations = edit::plan_set(&document, &query, &json!(1), false).unwrap();
+        assert_eq!(
+            edit::apply_to_toml(text, &operations).unwrap(),
+            "# Package metadata\npackage = 1\n\n[dependencies]\nanyhow = \"1.0\"\n"
+        );
+    }
+
+    #[test]
+    fn test_yaml_edit_preserves_comments() {
+        let text = "\
+# Service settings
+server:
+  host: 'localhost'   # bind address
+  port: 8080
+
+  # Optional features
+  features:
+    - metrics
+    - tracing
+";
+        let document: serde_json::Value = serde_yaml::from_str(text).unwrap();
+
+        let query = parser::parse_query("$.server.host").unwrap();
+        let operations = edit::plan_set(&document, &query, &json!("0.0.0.0"), false).unwrap();
+        assert_eq!(
+            edit::apply_to_yaml(text, &operations).unwrap(),
+            text.replace("'localhost'", "'0.0.0.0'")
+        );
+
+        let query = parser::parse_query("$.server.features[0]").unwrap();
+        let operations = edit::plan_delete(&document, &query).unwrap();
+        assert_eq!(
+            edit::apply_to_yaml(text, &operations).unwrap(),
+            text.replace("    - metrics\n", "")
+        );
+
+        let query = parser::parse_query("$.server.tls.enabled").unwrap();
+        let operations = edit::plan_set(&document, &query, &json!(true), true).unwrap();
+        assert_eq!(
+            edit::apply_to_yaml(text, &operations).unwrap(),
+            format!("{}  tls:\n    enabled: true\n", text)
+        );
+
+        // New lines take the line ending of the file
+        let crlf = text.replace('\n', "\r\n");
+        assert_eq!(
+            edit::apply_to_yaml(&crlf, &operations).unwrap(),
+            format!("{}  tls:\r\n    enabled: true\r\n", crlf)
+        );
+    }
+
+    #[test]
+    fn test_write_in_place_keeps_backup_and_permissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "version = \"0.1.0\"\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        crate::write_in_place(&path, "version = \"0.2.0\"\n", Some(".bak")).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "version = \"0.2.0\"\n");
+        assert_eq!(std::fs::read_to_string(dir.path().join("config.toml.bak")).unwrap(), "version = \"0.1.0\"\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+        // Only the file and its backup are left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        // A bare --backup never takes the query that follows as its suffix
+        use clap::Parser;
+        let cli = crate::Cli::try_parse_from(["decode", "set", "-w", "--backup", "$.version", "\"9\"", "-f", "x.toml"]).unwrap();
+        assert_eq!(cli.backup.as_deref(), Some(".bak"));
+        let cli = crate::Cli::try_parse_from(["decode", "set", "-w", "--backup=.orig", "$.version", "\"9\"", "-f", "x.toml"]).unwrap();
+        assert_eq!(cli.backup.as_deref(), Some(".orig"));
+    }
+
+    #[test]
+    fn test_emit_json_patch() {
+        let json = json!({"server": {"host": "localhost"}, "tags": ["a", "b", "c"]});
+
+        let query = parser::parse_query("$.tags[0,2]").unwrap();
+        let operations = edit::plan_delete(&json, &query).unwrap();
+        assert_eq!(edit::to_patch(&operations), json!([
+            {"op": "remove", "path": "/tags/2"},
+            {"op": "remove", "path": "/tags/0"}
+        ]));
+
+        let query = parser::parse_query("$.server.tls.enabled").unwrap();
+        let operations = edit::plan_set(&json, &query, &json!(true), true).unwrap();
+        assert_eq!(edit::to_patch(&operations), json!([
+            {"op": "add", "path": "/server/tls", "value": {"enabled": true}}
+        ]));
+    }
+
+    #[test]
+    fn test_apply_json_patch() {
+        let mut json = json!({"a": {"b": 1}, "list": [1, 2], "old": "x"});
+        let patch = json!([
+            {"op": "test", "path": "/a/b", "value": 1},
+            {"op": "add", "path": "/a/c", "value": 2},
+            {"op": "add", "path": "/list/1", "value": 5},
+            {"op": "add", "path": "/list/-", "value": 9},
+            {"op": "replace", "path": "/a/b", "value": 3},
+            {"op": "move", "from": "/old", "path": "/a/old"},
+            {"op": "copy", "from": "/a/c", "path": "/copied"},
+            {"op": "remove", "path": "/list/0"}
+        ]);
+
+        let operations = edit::plan_patch(&json, &patch).unwrap();
+        edit::apply(&mut json, &operations).unwrap();
+        assert_eq!(json, json!({"a": {"b": 3, "c": 2, "old": "x"}, "list": [5, 2, 9], "copied": 2}));
+
+        // A failing test rejects the whole patch
+        let patch = json!([{"op": "remove", "path": "/a"}, {"op": "test", "path": "/copied", "value": 3}]);
+        assert!(edit::plan_patch(&json, &patch).is_err());
+        let patch = json!([{"op": "move", "from": "/a", "path": "/a/inner"}]);
+        assert!(edit::plan_patch(&json, &patch).is_err());
+
+        // Numbers are tested by value, integers exactly
+        let patch = json!([{"op": "test", "path": "/list", "value": [5.0, 2, 9]}]);
+        assert!(edit::plan_patch(&json, &patch).unwrap().is_empty());
+        let patch = json!([{"op": "test", "path": "/big", "value": 9007199254740992u64}]);
+        assert!(edit::plan_patch(&json!({"big": 9007199254740993u64}), &patch).is_err());
+    }
+
+    #[test]
+    fn test_diff_documents() {
+        let left = json!({"name": "demo", "port": 8080, "ratio": 1, "tags": ["a", "b"], "old": true});
+        let right = json!({"name": "demo", "port": 9090, "ratio": 1.0, "tags": ["a"], "new": {"x": 1}});
+
+        let changes = diff::diff(&left, &right, &[]);
+
+        assert_eq!(diff::to_text(&changes), "\
+~ $['port']: 8080 -> 9090
+- $['tags'][1]: \"b\"
+- $['old']: true
++ $['new']: {\"x\":1}
+");
+        assert_eq!(diff::to_json(&changes)[0], json!({"change": "changed", "path": "$['port']", "old": 8080, "new": 9090}));
+        assert!(diff::diff(&left, &left, &[]).is_empty());
+
+        // Integers above 2^53 are equal as floats but still differ
+        assert_eq!(diff::diff(&json!(9007199254740993u64), &json!(9007199254740992u64), &[]).len(), 1);
+        assert!(diff::diff(&json!(-3), &json!(-3.0), &[]).is_empty());
+    }
+
+    #[test]
+    fn test_root_query() {
+        let json = json!({"a": 1});
+        let query = parser::parse_query("$").unwrap();
+        assert_eq!(engine::apply_query(&json, &query).unwrap(), json);
+
+        // The whole query must parse, so a mistyped path is never read as `$`
+        for text in ["$['x']", "$.a[", "$garbage", "$.a garbage"] {
+            assert!(parser::parse_query(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_deep_merge_array_strategies() {
+        let base = json!({
+            "server": {"host": "localhost", "port": 8080},
+            "workers": [{"name": "a", "size": 1}, {"name": "b", "size": 1}]
+        });
+        let overlay = json!({
+            "server": {"port": 443},
+            "workers": [{"name": "b", "size": 4}, {"name": "c", "size": 2}]
+        });
+
+        let mut merged = base.clone();
+        merge::deep_merge(&mut merged, overlay.clone(), merge::ArrayMerge::Replace);
+        assert_eq!(merged["server"], json!({"host": "localhost", "port": 443}));
+        assert_eq!(merged["workers"], overlay["workers"]);
+
+        let mut merged = base.clone();
+        merge::deep_merge(&mut merged, overlay.clone(), merge::ArrayMerge::Append);
+        assert_eq!(merged["workers"].as_array().unwrap().len(), 4);
+
+        let mut merged = base.clone();
+        merge::deep_merge(&mut merged, overlay, merge::ArrayMerge::ByKey("name"));
+        assert_eq!(merged["workers"], json!([
+            {"name": "a", "size": 1}, {"name": "b", "size": 4}, {"name": "c", "size": 2}
+        ]));
+    }
+
+    #[test]
+    fn test_expand_file_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.yaml", "a.yaml", "c.toml"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let pattern = dir.path().join("*.yaml");
+        let plain = dir.path().join("c.toml");
+
+        let files = crate::expand_files(&[pattern, plain.clone()]).unwrap();
+        assert_eq!(files, vec![dir.path().join("a.yaml"), dir.path().join("b.yaml"), plain]);
+
+        assert!(crate::expand_files(&[dir.path().join("*.json")]).is_err());
+    }
+
+    #[test]
+    fn test_walk_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a/app.yaml", "b/app.json", "b/notes.md", "vendor/lib.toml"] {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        std::fs::write(dir.path().join(".gitignore"), "vendor/\n").unwrap();
+        let root = vec![dir.path().to_path_buf()];
+
+        let files = crate::walk_directories(&root, &[], &[]).unwrap();
+        assert_eq!(files, vec![dir.path().join("a/app.yaml"), dir.path().join("b/app.json")]);
+
+        let files = crate::walk_directories(&root, &["*.json".to_string()], &[]).unwrap();
+        assert_eq!(files, vec![dir.path().join("b/app.json")]);
+
+        let files = crate::walk_directories(&root, &[], &["a/".to_string()]).unwrap();
+        assert_eq!(files, vec![dir.path().join("b/app.json")]);
+    }
+
+    #[test]
+    fn test_sniff_input_format() {
+        assert_eq!(input::sniff_format("  {\"a\": [1, 2]}").unwrap(), InputFormat::Json);
+        assert_eq!(input::sniff_format("# config\nserver:\n  port: 8080\n").unwrap(), InputFormat::Yaml);
+        assert_eq!(input::sniff_format("---\n- a\n- b\n").unwrap(), InputFormat::Yaml);
+        assert_eq!(input::sniff_format("[[source]]\nname = \"pypi\"\n").unwrap(), InputFormat::Toml);
+        assert_eq!(input::sniff_format("[\"a\"]\n").unwrap(), InputFormat::Json);
+        // Broken JSON is still JSON, so its own parser reports the error
+        assert_eq!(input::sniff_format("{\"a\":").unwrap(), InputFormat::Json);
+        let err = input::parse_input("{\"a\":", InputFormat::Json, &input::InputOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("JSON"));
+        // A TOML string containing YAML-like lines is still TOML
+        assert_eq!(input::sniff_format("text = \"\"\"\nkey: value\n\"\"\"\n").unwrap(), InputFormat::Toml);
+
+        assert!(input::sniff_format("").is_err());
+        let err = input::sniff_format("a = 1\nb: 2\n").unwrap_err();
+        assert!(err.to_string().contains("--input-format"));
+    }
+
+    #[test]
+    fn test_jsonc_and_json5_input() {
+        let text = "{\n  // editor settings\n  \"url\": \"http://example.com\", /* trailing */\n  \"tabs\": [2, 4,],\n}\n";
+        assert_eq!(input::parse_input(text, InputFormat::Jsonc, &input::InputOptions::default()).unwrap(), json!({"url": "http://example.com", "tabs": [2, 4]}));
+        assert_eq!(input::sniff_format(text).unwrap(), InputFormat::Jsonc);
+        // Blanked characters keep every line and column in place
+        assert_eq!(input::strip_jsonc(text).lines().count(), text.lines().count());
+        assert_eq!(input::strip_jsonc(text).len(), text.len());
+
+        let err = input::parse_input(text, InputFormat::Json, &input::InputOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("--lenient"));
+
+        let text = "{unquoted: 'single', hex: 0x1F, list: [1, 2,],}";
+        assert_eq!(input::parse_input(text, InputFormat::Json5, &input::InputOptions::default()).unwrap(), json!({"unquoted": "single", "hex": 31, "list": [1, 2]}));
+    }
+
+    #[test]
+    fn test_csv_input() {
+        let text = "name,age,zip,active\nAlice,31,02134,true\n\"Smith, J\",40,,false\n";
+        let options = input::InputOptions::default();
+        assert_eq!(input::parse_input(text, InputFormat::Csv, &options).unwrap(), json!([
+            {"name": "Alice", "age": "31", "zip": "02134", "active": "true"},
+            {"name": "Smith, J", "age": "40", "zip": "", "active": "false"}
+        ]));
+
+        // Leading zeros keep identifiers such as zip codes as strings
+        let options = input::InputOptions { infer_types: true, ..Default::default() };
+        let records = input::parse_input(text, InputFormat::Csv, &options).unwrap();
+        assert_eq!(records[0], json!({"name": "Alice", "age": 31, "zip": "02134", "active": true}));
+        assert_eq!(records[1]["zip"], json!(null));
+
+        let options = input::InputOptions { header: false, delimiter: Some(b';'), quote: b'\'', ..Default::default() };
+        assert_eq!(input::parse_input("a;'b;c'\n", InputFormat::Csv, &options).unwrap(), json!([["a", "b;c"]]));
+        assert_eq!(input::parse_input("a\tb\n1\t2\n", InputFormat::Tsv, &input::InputOptions::default()).unwrap(), json!([{"a": "1", "b": "2"}]));
+
+        // Repeated headers are numbered instead of overwriting each other
+        assert_eq!(input::parse_input("a,a\n1,2\n", InputFormat::Csv, &input::InputOptions::default()).unwrap(), json!([{"a": "1", "a_2": "2"}]));
+        assert_eq!(input::parse_input("a,a,a_2,a\n1,2,3,4\n", InputFormat::Csv, &input::InputOptions::default()).unwrap(),
+            json!([{"a": "1", "a_3": "2", "a_2": "3", "a_4": "4"}]));
+    }
+
+    #[test]
+    fn test_xml_input() {
+        let text = r#"<?xml version="1.0"?>
+<project xmlns="http://maven.apache.org/POM/4.0.0" xmlns:m="urn:meta">
+  <!-- dependencies -->
+  <dependencies>
+    <dependency scope="test"><artifactId>junit</artifactId><optional/></dependency>
+    <dependency><artifactId>serde</artifactId></dependency>
+  </dependencies>
+  <m:note lang="en">keep <![CDATA[<this>]]></m:note>
+  <module>core</module>
+</project>"#;
+        let document = input::parse_input(text, InputFormat::Xml, &input::InputOptions::default()).unwrap();
+        assert_eq!(document, json!({"project": {
+            "dependencies": {"dependency": [
+                {"@scope": "test", "artifactId": "junit", "optional": null},
+                {"artifactId": "serde"}
+            ]},
+            "m:note": {"@lang": "en", "#text": "keep <this>"},
+            "module": "core"
+        }}));
+        assert_eq!(input::sniff_format(text).unwrap(), InputFormat::Xml);
+
+        // Elements named with --xml-array are arrays even when they appear once
+        let options = input::InputOptions { xml_arrays: vec!["module".to_string()], ..Default::default() };
+        let document = input::parse_input(text, InputFormat::Xml, &options).unwrap();
+        assert_eq!(document["project"]["module"], json!(["core"]));
+    }
+
+    #[test]
+    fn test_ini_properties_and_env_input() {
+        let options = input::InputOptions::default();
+        let expand = input::InputOptions { expand_keys: true, ..Default::default() };
+
+        let text = "name = app\n; comment\n[server]\nhost = example.com\npath = C:\\data\n[db.primary]\nport = 5432\n";
+        assert_eq!(input::parse_input(text, InputFormat::Ini, &options).unwrap(), json!({
+            "name": "app",
+            "server": {"host": "example.com", "path": "C:\\data"},
+            "db.primary": {"port": "5432"}
+        }));
+        assert_eq!(input::parse_input(text, InputFormat::Ini, &expand).unwrap()["db"], json!({"primary": {"port": "5432"}}));
+        // Keys without a value, as in my.cnf, don't swallow the following lines
+        let text = "[mysqld]\nskip-networking\nport=3306\n[client]\nflag\nuser=app\n";
+        assert_eq!(input::parse_input(text, InputFormat::Ini, &options).unwrap(), json!({
+            "mysqld": {"skip-networking": null, "port": "3306"},
+            "client": {"flag": null, "user": "app"}
+        }));
+
+        let text = "# comment\nlog.level=INFO\nlog.file : app.log\ngreeting Hello \\\n    world\nname=caf\\u00e9\n";
+        assert_eq!(input::parse_input(text, InputFormat::Properties, &options).unwrap(), json!({
+            "log.level": "INFO", "log.file": "app.log", "greeting": "Hello world", "name": "café"
+        }));
+        assert_eq!(input::parse_input(text, InputFormat::Properties, &expand).unwrap()["log"], json!({"level": "INFO", "file": "app.log"}));
+        assert!(input::parse_input("a=1\na.b=2\n", InputFormat::Properties, &expand).is_err());
+        assert_eq!(input::parse_input("emoji=\\ud83d\\ude00\n", InputFormat::Properties, &options).unwrap(), json!({"emoji": "😀"}));
+        assert!(input::parse_input("lone=\\ud83d\n", InputFormat::Properties, &options).is_err());
+
+        let text = "# comment\nexport URL=http://x # primary\nRAW='a$b'\nMULTI=\"one\ntwo\\tthree\"\nHOME_DIR=${HOME}\n";
+        assert_eq!(input::parse_input(text, InputFormat::Env, &options).unwrap(), json!({
+            "URL": "http://x", "RAW": "a$b", "MULTI": "one\ntwo\tthree", "HOME_DIR": "${HOME}"
+        }));
+        assert_eq!(input::format_from_extension(std::path::Path::new("config/.env.local")), Some(InputFormat::Env));
+        assert_eq!(input::format_from_extension(std::path::Path::new("app.properties")), Some(InputFormat::Properties));
+    }
+
+    #[test]
+    fn test_hcl_input() {
+        let text = r#"
+# Network
+module "vpc" {
+  source  = "terraform-aws-modules/vpc/aws"
+  version = "5.1.2"
+  cidr    = var.cidr
+}
+
+resource "aws_security_group" "web" {
+  ingress {
+    from_port = 80
+  }
+  ingress {
+    from_port = 443
+  }
+}
+"#;
+        let document = input::parse_input(text, InputFormat::Hcl, &input::InputOptions::default()).unwrap();
+        assert_eq!(document, json!({
+            "module": {"vpc": {"source": "terraform-aws-modules/vpc/aws", "version": "5.1.2", "cidr": "${var.cidr}"}},
+            "resource": {"aws_security_group": {"web": {"ingress": [{"from_port": 80}, {"from_port": 443}]}}}
+        }));
+        assert_eq!(input::sniff_format(text).unwrap(), InputFormat::Hcl);
+        assert_eq!(input::sniff_format("region = \"eu-west-1\"\n").unwrap(), InputFormat::Toml);
+
+        let query = parser::parse_query("$.module[*].source").unwrap();
+        assert_eq!(engine::apply_query(&document, &query).unwrap(), json!(["terraform-aws-modules/vpc/aws"]));
+    }
+
+    #[test]
+    fn test_msgpack_and_cbor() {
+        let options = input::InputOptions::default();
+
+        // {"id": 7, 1: "one", "blob": bin(01 02 ff), "ext": ext(5, aa)}
+        let msgpack = b"\x84\xa2id\x07\x01\xa3one\xa4blob\xc4\x03\x01\x02\xff\xa3ext\xd4\x05\xaa";
+        let document = input::parse_bytes(msgpack, InputFormat::Msgpack, &options).unwrap();
+        assert_eq!(document, json!({"id": 7, "1": "one", "blob": "AQL/", "ext": {"type": 5, "data": "qg=="}}));
+        assert_eq!(input::determine_input_format(&None, None, msgpack).unwrap(), InputFormat::Msgpack);
+
+        // {"n": 1, 2: h'0102', "t": 1(1700000000)}
+        let cbor = b"\xa3\x61n\x01\x02\x42\x01\x02\x61t\xc1\x1a\x65\x53\xf1\x00";
+        let document = input::parse_bytes(cbor, InputFormat::Cbor, &options).unwrap();
+        assert_eq!(document, json!({"n": 1, "2": "AQI=", "t": 1700000000}));
+        assert_eq!(input::determine_input_format(&None, None, cbor).unwrap(), InputFormat::Cbor);
+
+        let value = json!({"name": "decode", "sizes": [1, -2, 3.5], "nested": {"ok": true, "none": null}});
+        let encoded = output::to_msgpack(&value).unwrap();
+        assert_eq!(input::parse_bytes(&encoded, InputFormat::Msgpack, &options).unwrap(), value);
+        let encoded = output::to_cbor(&value).unwrap();
+        assert_eq!(input::parse_bytes(&encoded, InputFormat::Cbor, &options).unwrap(), value);
+
+        let err = input::parse_bytes(b"\xff{}", InputFormat::Json, &options).unwrap_err();
+        assert!(err.to_string().contains("UTF-8"));
+    }
+
+    #[test]
+    fn test_yaml_quotes_yaml_1_1_booleans() {
+        let text = "country: GB\nafter: 1\n";
+        let document: serde_json::Value = serde_yaml::from_str(text).unwrap();
+        for (query, value, expected) in [
+            ("$.after", json!("yes"), "after: \"yes\"\n"),
+            ("$.country", json!("NO"), "country: \"NO\"\n"),
+            ("$.after", json!({"flag": "off"}), "after: {flag: \"off\"}\n"),
+            ("$.extra", json!({"flag": "on", "list": ["n", {"x": "Y"}]}), "extra:\n  flag: \"on\"\n  list:\n  - \"n\"\n  - x: \"Y\"\n"),
+        ] {
+            let query = parser::parse_query(query).unwrap();
+            let operations = edit::plan_set(&document, &query, &value, true).unwrap();
+            let updated = edit::apply_to_yaml(text, &operations).unwrap();
+            assert!(updated.contains(expected), "{}", updated);
+        }
+    }
+
+    #[test]
+    fn test_yaml_replace_block_sequence() {
+        let query = parser::parse_query("$.list").unwrap();
+        for text in ["list:\n- a\n- b\nz: 1\n", "list:\n  - a\n  - b\nz: 1\n"] {
+            let document: serde_json::Value = serde_yaml::from_str(text).unwrap();
+            for value in [json!(["q", "r"]), json!([{"n": 1}]), json!("x")] {
+                let operations = edit::plan_set(&document, &query, &value, false).unwrap();
+                let updated = edit::apply_to_yaml(text, &operations).unwrap();
+                let parsed: serde_json::Value = serde_yaml::from_str(&updated).unwrap();
+                assert_eq!(parsed, json!({"list": value, "z": 1}), "{}", updated);
+            }
+        }
+    }
+}